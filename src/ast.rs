@@ -1,5 +1,18 @@
 // src/ast.rs
 
+// Byte offsets of a node in the source text, as captured by the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub name: String,
@@ -10,71 +23,164 @@ pub struct Program {
 #[derive(Debug, Clone)]
 pub enum Declaration {
     VariableDecl {
-        names: Vec<String>,
+        // Each name may have its own initial value, as in `let a = 1, b = 2: Int;`
+        names: Vec<(String, Span, Option<Expression>)>,
         type_spec: Expression,
+        // Initial value of every name without its own; a `{...}` list for an array
+        initializer: Option<Expression>,
+        span: Span,
     },
     ConstDecl {
         name: String,
-        type_name: String,
+        name_span: Span,
+        type_spec: Expression,
         value: Expression,
+        span: Span,
+    },
+    // Procedures are functions without a return type
+    Function {
+        name: String,
+        name_span: Span,
+        params: Vec<Parameter>,
+        return_type: Option<String>,
+        declarations: Vec<Declaration>,
+        body: Vec<Statement>,
+        span: Span,
+    },
+    // A declaration the parser could not make sense of
+    Error {
+        span: Span,
     },
 }
 
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub type_name: String,
+    pub span: Span, // Span of the name
+}
+
 #[derive(Debug, Clone)]
 pub enum Statement {
     Assignment {
         target: Variable,
         value: Expression,
+        span: Span,
     },
-    IfElse {
-        condition: Condition,
-        if_branch: Vec<Statement>,
+    // `if` followed by any number of `else if` branches; the first branch whose
+    // condition holds runs, or the else branch when none does
+    IfChain {
+        branches: Vec<IfBranch>,
         else_branch: Vec<Statement>,
+        span: Span,
     },
     DoWhile {
         condition: Condition,
         body: Vec<Statement>,
+        span: Span,
+    },
+    While {
+        condition: Condition,
+        body: Vec<Statement>,
+        span: Span,
     },
+    // Runs the body for start, start + step, ... up to end; afterwards the variable
+    // holds the last value the body ran with, or start if the body never ran
     For {
         var: String,
+        var_span: Span,
         start: Expression,
         end: Expression,
         step: Expression,
         body: Vec<Statement>,
+        span: Span,
+    },
+    // Runs the first case with a label equal to the subject, or the default
+    Switch {
+        subject: Expression,
+        cases: Vec<SwitchCase>,
+        default: Option<Vec<Statement>>,
+        span: Span,
     },
     Input {
         var: String,
+        var_span: Span,
+        span: Span,
     },
     Output {
         expressions: Vec<Expression>,
+        span: Span,
+    },
+    // Call of a procedure, or of a function whose result is discarded
+    Call {
+        name: String,
+        name_span: Span,
+        args: Vec<Expression>,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+    // Leave the innermost loop, or go on with its next iteration
+    Break {
+        span: Span,
+    },
+    Continue {
+        span: Span,
+    },
+    // A statement the parser could not make sense of
+    Error {
+        span: Span,
     },
 }
 
 #[derive(Debug, Clone)]
 pub enum Expression {
     Var(Variable),
-    Integer(i32),
-    Float(f32),
-    String(String),
-    Type(String),
+    Integer(i32, Span),
+    Float(f32, Span),
+    Bool(bool, Span),
+    String(String, Span),
+    Type(String, Span),
+    // `[T; size]`, where T is a type name or another array type and the size is
+    // a constant expression
     ArrayType {
-        type_name: String,
-        size: i32,
+        element: Box<Expression>,
+        size: Box<Expression>,
+        span: Span,
+    },
+    // `{a, b, c}` initializer of an array; rows of a multi-dimensional array nest
+    ArrayLiteral {
+        elements: Vec<Expression>,
+        span: Span,
     },
     Binary {
         left: Box<Expression>,
         op: BinaryOp,
         right: Box<Expression>,
+        span: Span,
+    },
+    Not(Box<Expression>, Span),
+    Negate(Box<Expression>, Span),
+    // Unary plus, which leaves a number unchanged
+    Plus(Box<Expression>, Span),
+    Call {
+        name: String,
+        name_span: Span,
+        args: Vec<Expression>,
+        span: Span,
     },
-    Not(Box<Expression>),
     Literal(Box<Expression>),  // Use Box to break the recursive definition
 }
 #[derive(Debug, Clone)]
 pub enum Variable {
-    Simple(String),
+    Simple(String, Span),
+    // One index per dimension, outermost first: `m[i][j]`
     Array {
         name: String,
-        index: Box<Expression>,
+        indices: Vec<Expression>,
+        span: Span,
     },
 }
 
@@ -84,6 +190,9 @@ pub enum BinaryOp {
     Subtract,
     Multiply,
     Divide,
+    Modulo,    // `%` or `MOD`, on Int operands
+    IntDivide, // `DIV`, on Int operands
+    Power,     // `**`
     LessThan,
     GreaterThan,
     LessEqual,
@@ -94,7 +203,103 @@ pub enum BinaryOp {
     Or,
 }
 
+impl BinaryOp {
+    // Operators that fail when their right operand is zero
+    pub fn divides(&self) -> bool {
+        matches!(self, BinaryOp::Divide | BinaryOp::Modulo | BinaryOp::IntDivide)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Condition {
     Expr(Expression),
-}
\ No newline at end of file
+}
+
+// `if (condition) then { ... }`
+#[derive(Debug, Clone)]
+pub struct IfBranch {
+    pub condition: Condition,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+// `case 1, 2: { ... }`; the labels are constant Int expressions
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub labels: Vec<Expression>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+impl Declaration {
+    pub fn span(&self) -> Span {
+        match self {
+            Declaration::VariableDecl { span, .. }
+            | Declaration::ConstDecl { span, .. }
+            | Declaration::Function { span, .. }
+            | Declaration::Error { span } => *span,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Assignment { span, .. }
+            | Statement::IfChain { span, .. }
+            | Statement::DoWhile { span, .. }
+            | Statement::While { span, .. }
+            | Statement::For { span, .. }
+            | Statement::Switch { span, .. }
+            | Statement::Input { span, .. }
+            | Statement::Output { span, .. }
+            | Statement::Call { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::Break { span }
+            | Statement::Continue { span }
+            | Statement::Error { span } => *span,
+        }
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Var(var) => var.span(),
+            Expression::Integer(_, span)
+            | Expression::Float(_, span)
+            | Expression::Bool(_, span)
+            | Expression::String(_, span)
+            | Expression::Type(_, span)
+            | Expression::ArrayType { span, .. }
+            | Expression::ArrayLiteral { span, .. }
+            | Expression::Binary { span, .. }
+            | Expression::Not(_, span)
+            | Expression::Negate(_, span)
+            | Expression::Plus(_, span)
+            | Expression::Call { span, .. } => *span,
+            Expression::Literal(inner) => inner.span(),
+        }
+    }
+
+    // Element type name and dimension sizes, outermost first, of a type specifier
+    pub fn type_shape(&self) -> Option<(&str, Vec<&Expression>)> {
+        match self {
+            Expression::Type(type_name, _) => Some((type_name, Vec::new())),
+            Expression::ArrayType { element, size, .. } => {
+                let (type_name, mut sizes) = element.type_shape()?;
+                sizes.insert(0, size.as_ref());
+                Some((type_name, sizes))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Variable {
+    pub fn span(&self) -> Span {
+        match self {
+            Variable::Simple(_, span) | Variable::Array { span, .. } => *span,
+        }
+    }
+}
@@ -0,0 +1,234 @@
+// src/cfg.rs
+
+use crate::ast::{Condition, Expression, IfBranch, Program, Statement, SwitchCase, Variable};
+
+// Index of the node control starts from; the program ends at EXIT.
+// Only the main program is lowered, function bodies are not analyzed.
+pub const ENTRY: usize = 0;
+pub const EXIT: usize = 1;
+
+// One step of the program as seen by the data-flow analyses
+#[derive(Debug)]
+pub enum Node<'a> {
+    Entry,
+    Exit,
+    // Point where several paths meet; has no effect of its own
+    Join,
+    Assign {
+        target: &'a Variable,
+        value: &'a Expression,
+    },
+    Input {
+        var: &'a str,
+    },
+    Output {
+        expressions: &'a [Expression],
+    },
+    // Successors are [taken when true, taken when false]
+    Branch {
+        condition: &'a Expression,
+    },
+    // Successors are the cases in order, then the default (or what follows the switch)
+    Switch {
+        subject: &'a Expression,
+        cases: &'a [SwitchCase],
+    },
+    // Start, end and step of a `for` loop are evaluated once, before the first test
+    ForInit {
+        var: &'a str,
+        start: &'a Expression,
+        end: &'a Expression,
+        step: &'a Expression,
+    },
+    // Successors are [enter the body, leave the loop]
+    ForTest,
+    ForStep {
+        var: &'a str,
+    },
+    // Call of a procedure; the callee may change any global variable
+    Call {
+        args: &'a [Expression],
+    },
+    // A statement the parser could not make sense of; it may have done anything
+    Unknown,
+}
+
+pub struct Cfg<'a> {
+    pub nodes: Vec<Node<'a>>,
+    pub successors: Vec<Vec<usize>>,
+    loops: Vec<(usize, usize)>, // Continue and break targets of the enclosing loops, innermost last
+}
+
+impl<'a> Cfg<'a> {
+    pub fn build(program: &'a Program) -> Self {
+        let mut cfg = Cfg {
+            nodes: vec![Node::Entry, Node::Exit],
+            successors: vec![Vec::new(), Vec::new()],
+            loops: Vec::new(),
+        };
+        let last = cfg.lower_block(&program.statements, ENTRY);
+        cfg.add_edge(last, EXIT);
+        cfg
+    }
+
+    fn add_node(&mut self, node: Node<'a>) -> usize {
+        self.nodes.push(node);
+        self.successors.push(Vec::new());
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        self.successors[from].push(to);
+    }
+
+    // Adds a node that control reaches from `from`
+    fn follow(&mut self, from: usize, node: Node<'a>) -> usize {
+        let id = self.add_node(node);
+        self.add_edge(from, id);
+        id
+    }
+
+    // Lowers the body of a loop whose `continue` goes to `next` and `break` to `after`
+    fn lower_loop_body(&mut self, body: &'a [Statement], from: usize, next: usize, after: usize) -> usize {
+        self.loops.push((next, after));
+        let body_exit = self.lower_block(body, from);
+        self.loops.pop();
+        body_exit
+    }
+
+    // Lowers a statement list entered from `from` and returns the node it leaves through
+    fn lower_block(&mut self, statements: &'a [Statement], from: usize) -> usize {
+        statements
+            .iter()
+            .fold(from, |from, stmt| self.lower_statement(stmt, from))
+    }
+
+    fn lower_statement(&mut self, stmt: &'a Statement, from: usize) -> usize {
+        match stmt {
+            Statement::Assignment { target, value, .. } => {
+                self.follow(from, Node::Assign { target, value })
+            }
+            Statement::IfChain {
+                branches,
+                else_branch,
+                ..
+            } => {
+                // Each condition is only tested when the ones before it are false
+                let mut exits = Vec::new();
+                let mut next = from;
+                for IfBranch {
+                    condition: Condition::Expr(condition),
+                    body,
+                    ..
+                } in branches
+                {
+                    let branch = self.follow(next, Node::Branch { condition });
+                    let then_entry = self.follow(branch, Node::Join);
+                    next = self.follow(branch, Node::Join);
+                    exits.push(self.lower_block(body, then_entry));
+                }
+                let else_exit = self.lower_block(else_branch, next);
+
+                let join = self.add_node(Node::Join);
+                for exit in exits.into_iter().chain([else_exit]) {
+                    self.add_edge(exit, join);
+                }
+                join
+            }
+            Statement::DoWhile {
+                condition: Condition::Expr(condition),
+                body,
+                ..
+            } => {
+                let head = self.follow(from, Node::Join);
+                let test = self.add_node(Node::Join);
+                let after = self.add_node(Node::Join);
+                let body_exit = self.lower_loop_body(body, head, test, after);
+                self.add_edge(body_exit, test);
+
+                let branch = self.follow(test, Node::Branch { condition });
+                self.add_edge(branch, head);
+                self.add_edge(branch, after);
+                after
+            }
+            Statement::While {
+                condition: Condition::Expr(condition),
+                body,
+                ..
+            } => {
+                let head = self.follow(from, Node::Join);
+                let branch = self.follow(head, Node::Branch { condition });
+                let body_entry = self.follow(branch, Node::Join);
+                let after = self.follow(branch, Node::Join);
+                let body_exit = self.lower_loop_body(body, body_entry, head, after);
+                self.add_edge(body_exit, head);
+                after
+            }
+            Statement::For {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                let init = self.follow(
+                    from,
+                    Node::ForInit {
+                        var,
+                        start,
+                        end,
+                        step,
+                    },
+                );
+                let test = self.follow(init, Node::ForTest);
+                let body_entry = self.follow(test, Node::Join);
+                let after = self.follow(test, Node::Join);
+                let step = self.add_node(Node::ForStep { var });
+                let body_exit = self.lower_loop_body(body, body_entry, step, after);
+                self.add_edge(body_exit, step);
+                self.add_edge(step, test);
+                after
+            }
+            Statement::Switch {
+                subject,
+                cases,
+                default,
+                ..
+            } => {
+                let switch = self.follow(from, Node::Switch { subject, cases });
+                let bodies = cases.iter().map(|case| case.body.as_slice());
+                let exits: Vec<usize> = bodies
+                    .chain([default.as_deref().unwrap_or_default()])
+                    .map(|body| {
+                        let entry = self.follow(switch, Node::Join);
+                        self.lower_block(body, entry)
+                    })
+                    .collect();
+
+                let join = self.add_node(Node::Join);
+                for exit in exits {
+                    self.add_edge(exit, join);
+                }
+                join
+            }
+            Statement::Input { var, .. } => self.follow(from, Node::Input { var }),
+            Statement::Output { expressions, .. } => self.follow(from, Node::Output { expressions }),
+            Statement::Call { args, .. } => self.follow(from, Node::Call { args }),
+            // Nothing follows a return, so the statements after it hang off an unreachable node
+            Statement::Return { .. } => {
+                self.add_edge(from, EXIT);
+                self.add_node(Node::Join)
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => {
+                // The analyzer rejects both outside a loop
+                if let Some(&(next, after)) = self.loops.last() {
+                    let target = if matches!(stmt, Statement::Break { .. }) { after } else { next };
+                    self.add_edge(from, target);
+                }
+                self.add_node(Node::Join)
+            }
+            Statement::Error { .. } => self.follow(from, Node::Unknown),
+        }
+    }
+}
@@ -0,0 +1,464 @@
+// src/constant_propagation.rs
+
+use crate::ast::{BinaryOp, Expression, Program, Span, Variable};
+use crate::cfg::{Cfg, Node, ENTRY};
+use crate::symbol_table::{DataType, EntityType, SymbolTable, Value};
+use std::collections::HashMap;
+
+// Variables known to hold the same value on every path reaching a program point.
+// A variable that is missing may hold different values, or none yet.
+type State = HashMap<String, Value>;
+
+// A read of a variable that yields the same value on every execution reaching it
+#[derive(Debug, Clone)]
+pub struct ConstantFact {
+    pub name: String,
+    pub value: Value,
+    pub span: Span,
+}
+
+#[derive(Debug, Default)]
+pub struct ConstantPropagation {
+    pub facts: Vec<ConstantFact>,
+    pub zero_divisors: Vec<Span>, // Divisors that are zero whenever they are evaluated
+}
+
+// Runs a forward data-flow analysis over the control-flow graph of the program.
+// Branches whose condition is constant only propagate along the edge they take,
+// so code that can never run produces no facts.
+pub fn analyze(program: &Program, symbol_table: &SymbolTable) -> ConstantPropagation {
+    let cfg = Cfg::build(program);
+    let mut states: Vec<Option<State>> = vec![None; cfg.nodes.len()];
+
+    // Global variables with a constant initializer hold it when the program starts
+    let initial = symbol_table.scopes[0]
+        .symbols
+        .values()
+        .filter(|entry| entry.entity_type == EntityType::Variable && entry.value != Value::Undefined)
+        .map(|entry| (entry.name.clone(), entry.value.clone()))
+        .collect();
+    states[ENTRY] = Some(initial);
+    let mut worklist = vec![ENTRY];
+
+    while let Some(node) = worklist.pop() {
+        let state = match &states[node] {
+            Some(state) => state.clone(),
+            None => continue,
+        };
+        let successors = feasible_successors(&cfg, node, &state, symbol_table);
+        let out = transfer(&cfg.nodes[node], state, symbol_table);
+
+        for successor in successors {
+            let merged = match &states[successor] {
+                Some(previous) => meet(previous, &out),
+                None => out.clone(),
+            };
+            if states[successor].as_ref() != Some(&merged) {
+                states[successor] = Some(merged);
+                worklist.push(successor);
+            }
+        }
+    }
+
+    let mut result = ConstantPropagation::default();
+    for (node, state) in cfg.nodes.iter().zip(&states) {
+        if let Some(state) = state {
+            // Values read around a call may be changed by the callee, so none are reported
+            let expressions = node_expressions(node);
+            if expressions.iter().any(|expr| contains_call(expr)) {
+                continue;
+            }
+            for expr in expressions {
+                collect(expr, state, symbol_table, &mut result);
+            }
+        }
+    }
+    result.facts.sort_by_key(|fact| fact.span.start);
+    result.zero_divisors.sort_by_key(|span| span.start);
+    result
+}
+
+// Keeps only the variables both states agree on
+fn meet(left: &State, right: &State) -> State {
+    left.iter()
+        .filter(|(name, value)| right.get(*name) == Some(*value))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+fn transfer(node: &Node, mut state: State, symbol_table: &SymbolTable) -> State {
+    // A called function may assign any global variable
+    if matches!(node, Node::Call { .. }) || node_expressions(node).into_iter().any(contains_call) {
+        state.clear();
+        return state;
+    }
+
+    match node {
+        Node::Assign {
+            target: Variable::Simple(name, _),
+            value,
+        } => {
+            let value = evaluate(value, &|name| lookup(&state, symbol_table, name));
+            assign(&mut state, symbol_table, name, value);
+        }
+        Node::ForInit { var, start, .. } => {
+            let value = evaluate(start, &|name| lookup(&state, symbol_table, name));
+            assign(&mut state, symbol_table, var, value);
+        }
+        Node::Input { var } | Node::ForStep { var } => {
+            state.remove(*var);
+        }
+        Node::Unknown => state.clear(),
+        // Array elements are not tracked
+        _ => {}
+    }
+    state
+}
+
+fn assign(state: &mut State, symbol_table: &SymbolTable, name: &str, value: Option<Value>) {
+    let data_type = match symbol_table.lookup(name) {
+        Some(entry) if entry.entity_type == EntityType::Variable => &entry.data_type,
+        _ => return,
+    };
+    // Stored values are widened the same way the interpreter does
+    match (data_type, value) {
+        (DataType::Float, Some(Value::Int(n))) => state.insert(name.to_string(), Value::Float(n as f32)),
+        (_, Some(value)) => state.insert(name.to_string(), value),
+        (_, None) => state.remove(name),
+    };
+}
+
+fn feasible_successors(cfg: &Cfg, node: usize, state: &State, symbol_table: &SymbolTable) -> Vec<usize> {
+    let successors = &cfg.successors[node];
+    let value_of = |expr: &Expression| evaluate(expr, &|name| lookup(state, symbol_table, name));
+    match &cfg.nodes[node] {
+        Node::Branch { condition } => match value_of(condition) {
+            Some(value) if is_true(&value) => return vec![successors[0]],
+            Some(_) => return vec![successors[1]],
+            None => {}
+        },
+        // Only the matching case runs, or the default when none matches
+        Node::Switch { subject, cases } => {
+            if let Some(value) = value_of(subject) {
+                let matching = cases
+                    .iter()
+                    .position(|case| case.labels.iter().any(|label| value_of(label).as_ref() == Some(&value)));
+                return vec![successors[matching.unwrap_or(cases.len())]];
+            }
+        }
+        _ => {}
+    }
+    successors.clone()
+}
+
+// The expressions a node evaluates, in evaluation order
+fn node_expressions<'a>(node: &Node<'a>) -> Vec<&'a Expression> {
+    match node {
+        Node::Assign { target, value } => match target {
+            Variable::Array { indices, .. } => indices.iter().chain([*value]).collect(),
+            Variable::Simple(..) => vec![*value],
+        },
+        Node::Output { expressions } => expressions.iter().collect(),
+        Node::Branch { condition } => vec![*condition],
+        Node::Switch { subject, .. } => vec![*subject],
+        Node::ForInit { start, end, step, .. } => vec![*start, *end, *step],
+        Node::Call { args } => args.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn contains_call(expr: &Expression) -> bool {
+    match expr {
+        Expression::Call { .. } => true,
+        Expression::Var(Variable::Array { indices, .. }) => indices.iter().any(contains_call),
+        Expression::Literal(inner)
+        | Expression::Not(inner, _)
+        | Expression::Negate(inner, _)
+        | Expression::Plus(inner, _) => {
+            contains_call(inner)
+        }
+        Expression::Binary { left, right, .. } => contains_call(left) || contains_call(right),
+        _ => false,
+    }
+}
+
+// Records the constant variable reads and zero divisors of an expression that is
+// evaluated in `state`
+fn collect(expr: &Expression, state: &State, symbol_table: &SymbolTable, result: &mut ConstantPropagation) {
+    let value_of = |expr: &Expression| evaluate(expr, &|name| lookup(state, symbol_table, name));
+
+    match expr {
+        Expression::Var(Variable::Simple(name, span)) => {
+            if let Some(value) = state.get(name) {
+                result.facts.push(ConstantFact {
+                    name: name.clone(),
+                    value: value.clone(),
+                    span: *span,
+                });
+            }
+        }
+        Expression::Var(Variable::Array { indices, .. }) => {
+            for index in indices {
+                collect(index, state, symbol_table, result);
+            }
+        }
+        Expression::Literal(inner)
+        | Expression::Not(inner, _)
+        | Expression::Negate(inner, _)
+        | Expression::Plus(inner, _) => {
+            collect(inner, state, symbol_table, result)
+        }
+        Expression::Binary { left, op, right, .. } => {
+            collect(left, state, symbol_table, result);
+
+            // The right operand is skipped when the left one already decides the result
+            let short_circuits = match (op, value_of(left)) {
+                (BinaryOp::And, Some(value)) => !is_true(&value),
+                (BinaryOp::Or, Some(value)) => is_true(&value),
+                _ => false,
+            };
+            if short_circuits {
+                return;
+            }
+            collect(right, state, symbol_table, result);
+
+            if op.divides() {
+                if let Some(Value::Int(0) | Value::Float(0.0)) = value_of(right) {
+                    result.zero_divisors.push(right.span());
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn lookup(state: &State, symbol_table: &SymbolTable, name: &str) -> Option<Value> {
+    match symbol_table.lookup(name) {
+        Some(entry) if matches!(entry.entity_type, EntityType::Constant | EntityType::Array { constant: true, .. }) => {
+            Some(entry.value.clone())
+        }
+        _ => state.get(name).cloned(),
+    }
+}
+
+fn is_true(value: &Value) -> bool {
+    matches!(value, Value::Bool(true))
+}
+
+// Folds an expression whose variables are resolved by `lookup`, following the
+// interpreter's rules. Anything that would fail at runtime (overflow, division by
+// zero) is left unevaluated.
+pub fn evaluate(expr: &Expression, lookup: &dyn Fn(&str) -> Option<Value>) -> Option<Value> {
+    match expr {
+        Expression::Integer(n, _) => Some(Value::Int(*n)),
+        Expression::Float(n, _) => Some(Value::Float(*n)),
+        Expression::Bool(b, _) => Some(Value::Bool(*b)),
+        Expression::String(s, _) => Some(Value::String(s.clone())),
+        Expression::Literal(inner) => evaluate(inner, lookup),
+        Expression::Var(Variable::Simple(name, _)) => lookup(name),
+        // Elements of constant arrays and characters of known strings
+        Expression::Var(Variable::Array { name, indices, .. }) => {
+            let mut value = lookup(name)?;
+            for index in indices {
+                let index = match evaluate(index, lookup)? {
+                    Value::Int(i) => usize::try_from(i).ok()?,
+                    _ => return None,
+                };
+                value = match value {
+                    Value::Array(elements) => elements.get(index)?.clone(),
+                    Value::String(s) => Value::String(s.chars().nth(index)?.to_string()),
+                    _ => return None,
+                };
+            }
+            (value != Value::Undefined).then_some(value)
+        }
+        Expression::Binary { left, op, right, .. } => {
+            let left = evaluate(left, lookup);
+
+            // Logical operators only need their right operand if the left one does not decide
+            match (op, &left) {
+                (BinaryOp::And, Some(value)) if !is_true(value) => return Some(Value::Bool(false)),
+                (BinaryOp::Or, Some(value)) if is_true(value) => return Some(Value::Bool(true)),
+                (BinaryOp::And | BinaryOp::Or, Some(_)) => {
+                    return evaluate(right, lookup).map(|value| Value::Bool(is_true(&value)))
+                }
+                _ => {}
+            }
+
+            fold_binary(op, left?, evaluate(right, lookup)?)
+        }
+        Expression::Not(inner, _) => match evaluate(inner, lookup)? {
+            Value::Bool(b) => Some(Value::Bool(!b)),
+            _ => None,
+        },
+        Expression::Negate(inner, _) => match evaluate(inner, lookup)? {
+            Value::Int(n) => n.checked_neg().map(Value::Int),
+            Value::Float(n) => Some(Value::Float(-n)),
+            _ => None,
+        },
+        Expression::Plus(inner, _) => match evaluate(inner, lookup)? {
+            value @ (Value::Int(_) | Value::Float(_)) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn fold_binary(op: &BinaryOp, left: Value, right: Value) -> Option<Value> {
+    let bool_value = |b: bool| Some(Value::Bool(b));
+
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => match op {
+            BinaryOp::Add => l.checked_add(r).map(Value::Int),
+            BinaryOp::Subtract => l.checked_sub(r).map(Value::Int),
+            BinaryOp::Multiply => l.checked_mul(r).map(Value::Int),
+            BinaryOp::Divide | BinaryOp::IntDivide => l.checked_div(r).map(Value::Int),
+            BinaryOp::Modulo => l.checked_rem(r).map(Value::Int),
+            BinaryOp::Power => u32::try_from(r).ok().and_then(|r| l.checked_pow(r)).map(Value::Int),
+            BinaryOp::LessThan => bool_value(l < r),
+            BinaryOp::GreaterThan => bool_value(l > r),
+            BinaryOp::LessEqual => bool_value(l <= r),
+            BinaryOp::GreaterEqual => bool_value(l >= r),
+            BinaryOp::Equal => bool_value(l == r),
+            BinaryOp::NotEqual => bool_value(l != r),
+            BinaryOp::And | BinaryOp::Or => None,
+        },
+        // Mixed operands are widened to Float
+        (left @ (Value::Int(_) | Value::Float(_)), right @ (Value::Int(_) | Value::Float(_))) => {
+            let (l, r) = (as_float(&left), as_float(&right));
+            match op {
+                BinaryOp::Add => Some(Value::Float(l + r)),
+                BinaryOp::Subtract => Some(Value::Float(l - r)),
+                BinaryOp::Multiply => Some(Value::Float(l * r)),
+                BinaryOp::Divide if r != 0.0 => Some(Value::Float(l / r)),
+                BinaryOp::Divide => None,
+                BinaryOp::Power => Some(Value::Float(l.powf(r))),
+                // Only defined on Int operands
+                BinaryOp::Modulo | BinaryOp::IntDivide => None,
+                BinaryOp::LessThan => bool_value(l < r),
+                BinaryOp::GreaterThan => bool_value(l > r),
+                BinaryOp::LessEqual => bool_value(l <= r),
+                BinaryOp::GreaterEqual => bool_value(l >= r),
+                BinaryOp::Equal => bool_value(l == r),
+                BinaryOp::NotEqual => bool_value(l != r),
+                BinaryOp::And | BinaryOp::Or => None,
+            }
+        }
+        (Value::String(l), Value::String(r)) => match op {
+            BinaryOp::Add => Some(Value::String(l + &r)),
+            BinaryOp::Equal => bool_value(l == r),
+            BinaryOp::NotEqual => bool_value(l != r),
+            _ => None,
+        },
+        (Value::Bool(l), Value::Bool(r)) => match op {
+            BinaryOp::Equal => bool_value(l == r),
+            BinaryOp::NotEqual => bool_value(l != r),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_float(value: &Value) -> f32 {
+    match value {
+        Value::Int(i) => *i as f32,
+        Value::Float(f) => *f,
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::ErrorCode;
+    use crate::symbol_table::Value;
+    use crate::{compile, CompilationResult};
+
+    // Values of the facts reported for reads of `name`
+    fn facts_for(result: &CompilationResult, name: &str) -> Vec<Value> {
+        result
+            .constant_facts
+            .iter()
+            .filter(|fact| fact.name == name)
+            .map(|fact| fact.value.clone())
+            .collect()
+    }
+
+    fn zero_divisions(result: &CompilationResult) -> usize {
+        result
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.code == Some(ErrorCode::DivisionByZero))
+            .count()
+    }
+
+    #[test]
+    fn zero_divisor_through_both_branches() {
+        let result = compile(
+            "MainPrgm p; Var let d, y: Int;
+             BeginPg {
+               if (y > 0) then { d := 0; } else { d := 0; }
+               y := 10 / d;
+             } EndPg;",
+        );
+        assert_eq!(zero_divisions(&result), 1);
+    }
+
+    #[test]
+    fn zero_divisor_through_switch() {
+        let result = compile(
+            "MainPrgm p; Var let d, y: Int;
+             BeginPg {
+               d := 0;
+               switch (d) {
+                 case 0: { y := 1; }
+                 case 1: { d := 5; }
+               }
+               y := 10 / d;
+             } EndPg;",
+        );
+        assert_eq!(zero_divisions(&result), 1);
+    }
+
+    #[test]
+    fn no_fact_after_call_changing_global() {
+        let result = compile(
+            "MainPrgm p; Var let g, y: Int;
+             function bump() { g := g + 1; }
+             BeginPg {
+               g := 1;
+               bump();
+               y := g;
+             } EndPg;",
+        );
+        assert!(result.succeeded());
+        assert!(facts_for(&result, "g").is_empty());
+    }
+
+    #[test]
+    fn no_fact_for_variable_modified_in_loop() {
+        let result = compile(
+            "MainPrgm p; Var let i, x, y: Int;
+             BeginPg {
+               x := 0;
+               for i from 1 to 3 step 1 {
+                 x := x + 1;
+               }
+               y := x;
+             } EndPg;",
+        );
+        assert!(result.succeeded());
+        assert!(facts_for(&result, "x").is_empty());
+    }
+
+    #[test]
+    fn fact_for_unchanged_variable() {
+        let result = compile(
+            "MainPrgm p; Var let x, y: Int;
+             BeginPg {
+               x := 4;
+               y := x * 2;
+             } EndPg;",
+        );
+        assert_eq!(facts_for(&result, "x"), [Value::Int(4)]);
+    }
+}
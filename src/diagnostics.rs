@@ -0,0 +1,327 @@
+// src/diagnostics.rs
+
+use crate::ast::Span;
+use crate::lexer::LineIndex;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    #[allow(dead_code)]
+    Note,
+}
+
+// Stable codes so that an error can be looked up and matched on by tooling.
+// New codes are appended, existing ones are never renumbered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    DuplicateDeclaration,
+    UnknownType,
+    UndeclaredIdentifier,
+    ConstantAssignment,
+    TypeMismatch,
+    IndexOutOfBounds,
+    NotAnArray,
+    DivisionByZero,
+    InvalidConstant,
+    UnexpectedToken,
+    UnexpectedEndOfInput,
+    InvalidCharacter,
+    MalformedIdentifier,
+    IntegerOverflow,
+    NotAFunction,
+    ArgumentCountMismatch,
+    MissingReturn,
+    ReturnOutsideFunction,
+    InvalidEscape,
+    UnterminatedString,
+    InitializerCountMismatch,
+    LoopControlOutsideLoop,
+    ZeroLoopStep,
+    LoopVariableAssignment,
+    DuplicateCaseLabel,
+    NestedFunction,
+    ArrayTooLarge,
+}
+
+impl ErrorCode {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::DuplicateDeclaration => "E0001",
+            ErrorCode::UnknownType => "E0002",
+            ErrorCode::UndeclaredIdentifier => "E0003",
+            ErrorCode::ConstantAssignment => "E0004",
+            ErrorCode::TypeMismatch => "E0005",
+            ErrorCode::IndexOutOfBounds => "E0006",
+            ErrorCode::NotAnArray => "E0007",
+            ErrorCode::DivisionByZero => "E0008",
+            ErrorCode::InvalidConstant => "E0009",
+            ErrorCode::UnexpectedToken => "E0010",
+            ErrorCode::UnexpectedEndOfInput => "E0011",
+            ErrorCode::InvalidCharacter => "E0012",
+            ErrorCode::MalformedIdentifier => "E0013",
+            ErrorCode::IntegerOverflow => "E0014",
+            ErrorCode::NotAFunction => "E0015",
+            ErrorCode::ArgumentCountMismatch => "E0016",
+            ErrorCode::MissingReturn => "E0017",
+            ErrorCode::ReturnOutsideFunction => "E0018",
+            ErrorCode::InvalidEscape => "E0019",
+            ErrorCode::UnterminatedString => "E0020",
+            ErrorCode::InitializerCountMismatch => "E0021",
+            ErrorCode::LoopControlOutsideLoop => "E0022",
+            ErrorCode::ZeroLoopStep => "E0023",
+            ErrorCode::LoopVariableAssignment => "E0024",
+            ErrorCode::DuplicateCaseLabel => "E0025",
+            ErrorCode::NestedFunction => "E0026",
+            ErrorCode::ArrayTooLarge => "E0027",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+// Extra source location attached to a diagnostic, e.g. "constant declared here"
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Label {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Label {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>, // Text printed next to the primary carets
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message: message.into(),
+            span,
+            label: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic::new(Severity::Error, message, span)
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, labels: Vec<Label>) -> Self {
+        self.secondary = labels;
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+// ANSI styles used when writing to a terminal
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const CYAN: &str = "\x1b[1;36m";
+const BLUE: &str = "\x1b[1;34m";
+
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    line_index: LineIndex,
+    color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file_name: &'a str, source: &'a str, color: bool) -> Self {
+        Renderer {
+            file_name,
+            source,
+            line_index: LineIndex::new(source),
+            color,
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    // Renders a diagnostic as
+    //
+    //   error[E0003]: Undeclared identifier: 'a'
+    //     --> program.minisoft:11:10
+    //      |
+    //   11 |     x := a + 10;
+    //      |          ^
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut output = String::new();
+
+        let (severity, style) = match diagnostic.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+            Severity::Note => ("note", CYAN),
+        };
+        let header = match diagnostic.code {
+            Some(code) => format!("{}[{}]", severity, code),
+            None => severity.to_string(),
+        };
+        writeln!(
+            output,
+            "{}{}",
+            self.paint(style, &header),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        )
+        .unwrap();
+
+        // Primary and secondary labels are shown together in source order
+        let mut labels = vec![(
+            diagnostic.span,
+            diagnostic.label.clone().unwrap_or_default(),
+            true,
+        )];
+        labels.extend(
+            diagnostic
+                .secondary
+                .iter()
+                .map(|label| (label.span, label.message.clone(), false)),
+        );
+        labels.sort_by_key(|(span, _, _)| span.start);
+
+        let last_line = labels
+            .iter()
+            .map(|(span, _, _)| self.line_index.line_column(span.start).0)
+            .max()
+            .unwrap_or(1);
+        let gutter = last_line.to_string().len();
+        let bar = self.paint(BLUE, "|");
+
+        let (line, column) = self.line_index.line_column(diagnostic.span.start);
+        writeln!(
+            output,
+            "{:gutter$}{} {}:{}:{}",
+            "",
+            self.paint(BLUE, "-->"),
+            self.file_name,
+            line,
+            column,
+            gutter = gutter
+        )
+        .unwrap();
+        writeln!(output, "{:gutter$} {}", "", bar, gutter = gutter).unwrap();
+
+        let mut previous_line = None;
+        for (span, message, primary) in &labels {
+            let (line, _) = self.line_index.line_column(span.start);
+            let text = self.line_text(line);
+
+            if previous_line != Some(line) {
+                if matches!(previous_line, Some(previous) if line > previous + 1) {
+                    writeln!(output, "{}", self.paint(BLUE, "...")).unwrap();
+                }
+                writeln!(
+                    output,
+                    "{} {} {}",
+                    self.paint(BLUE, &format!("{:>gutter$}", line, gutter = gutter)),
+                    bar,
+                    text
+                )
+                .unwrap();
+            }
+            previous_line = Some(line);
+
+            let (padding, width) = self.underline(text, line, *span);
+            let (marker, marker_style) = if *primary { ("^", style) } else { ("-", BLUE) };
+            let underline = marker.repeat(width);
+            let underline = if message.is_empty() {
+                underline
+            } else {
+                format!("{} {}", underline, message)
+            };
+            writeln!(
+                output,
+                "{:gutter$} {} {}{}",
+                "",
+                bar,
+                padding,
+                self.paint(marker_style, &underline),
+                gutter = gutter
+            )
+            .unwrap();
+        }
+
+        for note in &diagnostic.notes {
+            writeln!(
+                output,
+                "{:gutter$} {} {}: {}",
+                "",
+                self.paint(BLUE, "="),
+                self.paint(BOLD, "note"),
+                note,
+                gutter = gutter
+            )
+            .unwrap();
+        }
+
+        output
+    }
+
+    fn line_text(&self, line: usize) -> &str {
+        self.source
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("")
+            .trim_end_matches('\r')
+    }
+
+    // Whitespace that lines carets up with the span start, and the number of carets.
+    // Spans covering several lines are underlined up to the end of their first line.
+    fn underline(&self, text: &str, line: usize, span: Span) -> (String, usize) {
+        let line_start = self.line_index.line_start(line);
+        let start = (span.start - line_start).min(text.len());
+        let end = span.end.saturating_sub(line_start).clamp(start, text.len());
+
+        let padding: String = text[..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = text[start..end].chars().count().max(1);
+        (padding, width)
+    }
+}
@@ -1,123 +1,724 @@
-use crate::ast::{Program, Statement, Expression, Variable, BinaryOp, Literal};
-use crate::symbol_table::{SymbolTable, Value, EntityType};
+// src/interpreter.rs
 
-pub struct Interpreter {
+use crate::ast::{BinaryOp, Condition, Declaration, Expression, Program, Span, Statement, Variable};
+use crate::symbol_table::{DataType, EntityType, SymbolEntry, SymbolTable, Value, BUILTIN_LEN};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+// Calls nested deeper than this are reported instead of overflowing the stack;
+// the embedder has to give the interpreter a stack that holds this many calls
+// (the command line driver runs it on a thread with a large stack)
+const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span, // Statement that was executing when the error occurred
+}
+
+// Local declarations and body of a function
+type Function = (Vec<Declaration>, Vec<Statement>);
+
+// How control leaves a statement
+enum Flow {
+    Normal,
+    Return(Option<Value>),
+    Break,
+    Continue,
+}
+
+pub struct Interpreter<'a> {
     pub symbol_table: SymbolTable,
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    functions: HashMap<String, Rc<Function>>, // Every function, by name
+    call_depth: usize,
+    // Entries of every scope before the program runs, which each call starts from
+    initial: Vec<HashMap<String, SymbolEntry>>,
+    // Error raised inside a function called from an expression, which can only
+    // report a message; it replaces the error of the statement the call was in
+    call_failure: Option<RuntimeError>,
 }
 
-impl Interpreter {
-    pub fn new(symbol_table: SymbolTable) -> Self {
-        Interpreter { symbol_table }
+impl<'a> Interpreter<'a> {
+    // `input()` statements read lines from `input`, `output()` statements write to `output`
+    pub fn new(
+        symbol_table: SymbolTable,
+        input: Box<dyn BufRead + 'a>,
+        output: Box<dyn Write + 'a>,
+    ) -> Self {
+        // The analyzer leaves every entry at its value before the program runs:
+        // constants and initialized arrays hold their contents, the rest is undefined
+        let initial = symbol_table.scopes.iter().map(|scope| scope.symbols.clone()).collect();
+
+        Interpreter {
+            symbol_table,
+            input,
+            output,
+            functions: HashMap::new(),
+            call_depth: 0,
+            initial,
+            call_failure: None,
+        }
+    }
+
+    pub fn execute(&mut self, program: &Program) -> Result<(), RuntimeError> {
+        for decl in &program.declarations {
+            if let Declaration::Function { name, declarations, body, .. } = decl {
+                self.functions.insert(name.clone(), Rc::new((declarations.clone(), body.clone())));
+            }
+        }
+
+        self.initialize(&program.declarations)?;
+        self.execute_block(&program.statements)?;
+        self.output.flush().map_err(|e| RuntimeError {
+            message: format!("Cannot write output: {}", e),
+            span: Span::default(),
+        })
     }
-    
-    pub fn execute(&mut self, program: &Program) -> Result<(), String> {
-        for statement in &program.statements {
-            self.execute_statement(statement)?;
+
+    // Runs the initializers of scalar variables in declaration order; the contents
+    // of initialized arrays are constant and already in the symbol table
+    fn initialize(&mut self, declarations: &[Declaration]) -> Result<(), RuntimeError> {
+        for decl in declarations {
+            if let Declaration::VariableDecl {
+                names,
+                type_spec: Expression::Type(..),
+                initializer,
+                span,
+            } = decl
+            {
+                let at = |message| RuntimeError { message, span: *span };
+
+                // The shared initializer is evaluated once, for the names without their own
+                let shared = match initializer {
+                    Some(initializer) if names.iter().any(|(_, _, own)| own.is_none()) => {
+                        Some(self.evaluate_expression(initializer).map_err(at)?)
+                    }
+                    _ => None,
+                };
+                for (name, _, own) in names {
+                    let value = match (own, &shared) {
+                        (Some(own), _) => self.evaluate_expression(own).map_err(at)?,
+                        (None, Some(value)) => value.clone(),
+                        (None, None) => continue,
+                    };
+                    let data_type = match self.symbol_table.lookup(name) {
+                        Some(entry) => entry.data_type.clone(),
+                        None => return Err(at(format!("Undefined variable '{}'", name))),
+                    };
+                    let value = convert(&data_type, value, name).map_err(at)?;
+                    self.symbol_table.update_value(name, value).map_err(at)?;
+                }
+            }
         }
         Ok(())
     }
-    
-    fn execute_statement(&mut self, statement: &Statement) -> Result<(), String> {
+
+    fn execute_block(&mut self, statements: &[Statement]) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            match self.execute_statement(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // Errors are attributed to the innermost statement that failed, even inside a called function
+    fn execute_statement(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        self.run_statement(statement)
+            .map_err(|err| self.call_failure.take().unwrap_or(err))
+    }
+
+    fn run_statement(&mut self, statement: &Statement) -> Result<Flow, RuntimeError> {
+        let span = statement.span();
+        let at = |message: String| RuntimeError { message, span };
+
         match statement {
-            Statement::Assignment { target, value } => {
-                let evaluated = self.evaluate_expression(value)?;
-                
-                match target {
-                    Variable::Simple(name) => {
-                        self.symbol_table.update_value(name, evaluated)
-                    },
-                    Variable::Indexed { name, index } => {
-                        // Handle array assignments
-                        let idx_value = self.evaluate_expression(index)?;
-                        if let Value::Int(i) = idx_value {
-                            // You'll need to implement this method in your symbol table
-                            self.symbol_table.update_array_element(name, i as usize, evaluated)
-                        } else {
-                            Err(format!("Array index must be an Int"))
+            Statement::Assignment { target, value, .. } => {
+                self.execute_assignment(target, value).map_err(at)?;
+                Ok(Flow::Normal)
+            }
+            Statement::IfChain {
+                branches,
+                else_branch,
+                ..
+            } => {
+                for branch in branches {
+                    let holds = self
+                        .evaluate_condition(&branch.condition)
+                        .map_err(|message| RuntimeError { message, span: branch.span })?;
+                    if holds {
+                        return self.execute_block(&branch.body);
+                    }
+                }
+                self.execute_block(else_branch)
+            }
+            Statement::DoWhile { condition, body, .. } => {
+                loop {
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                    if !self.evaluate_condition(condition).map_err(at)? {
+                        break;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::While { condition, body, .. } => {
+                while self.evaluate_condition(condition).map_err(at)? {
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::For {
+                var,
+                start,
+                end,
+                step,
+                body,
+                ..
+            } => {
+                // Bounds and step are evaluated once, before the first iteration
+                let start = self.evaluate_expression(start).map_err(at)?;
+                let end = self.evaluate_expression(end).map_err(at)?;
+                let step = self.evaluate_expression(step).map_err(at)?;
+
+                // The loop counts up while the variable is at most `end` for a positive
+                // step, and down while it is at least `end` for a negative one
+                let past_end = match step {
+                    Value::Int(n) if n > 0 => BinaryOp::GreaterThan,
+                    Value::Int(n) if n < 0 => BinaryOp::LessThan,
+                    Value::Float(f) if f > 0.0 => BinaryOp::GreaterThan,
+                    Value::Float(f) if f < 0.0 => BinaryOp::LessThan,
+                    Value::Int(_) | Value::Float(_) => {
+                        return Err(at(format!("The step of the for loop over '{}' is zero", var)))
+                    }
+                    _ => return Err(at("The step of a for loop must be a number".to_string())),
+                };
+
+                // The body does not run when start is past the end
+                let start = self.coerce(var, start).map_err(at)?;
+                self.symbol_table.update_value(var, start).map_err(at)?;
+                let current = self.read_variable(var).map_err(at)?;
+                let done = self.apply_binary(&current, &past_end, &end).map_err(at)?;
+                if self.is_true(&done) {
+                    return Ok(Flow::Normal);
+                }
+
+                loop {
+                    match self.execute_block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+
+                    // The next value is tested before it is assigned, so the loop
+                    // variable keeps the last value the body ran with; a next value
+                    // that does not fit in an Int is past any Int end
+                    let current = self.read_variable(var).map_err(at)?;
+                    let next = match (&current, &step) {
+                        (Value::Int(n), Value::Int(step)) => match n.checked_add(*step) {
+                            Some(next) => Value::Int(next),
+                            None => break,
+                        },
+                        _ => self.apply_binary(&current, &BinaryOp::Add, &step).map_err(at)?,
+                    };
+                    let next = self.coerce(var, next).map_err(at)?;
+                    let done = self.apply_binary(&next, &past_end, &end).map_err(at)?;
+                    if self.is_true(&done) {
+                        break;
+                    }
+                    self.symbol_table.update_value(var, next).map_err(at)?;
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Switch { subject, cases, default, .. } => {
+                let subject = self.evaluate_expression(subject).map_err(at)?;
+                for case in cases {
+                    for label in &case.labels {
+                        if self.evaluate_expression(label).map_err(at)? == subject {
+                            return self.execute_block(&case.body);
                         }
                     }
                 }
+                match default {
+                    Some(default) => self.execute_block(default),
+                    None => Ok(Flow::Normal),
+                }
+            }
+            Statement::Input { var, .. } => {
+                self.execute_input(var).map_err(at)?;
+                Ok(Flow::Normal)
+            }
+            Statement::Output { expressions, .. } => {
+                self.execute_output(expressions).map_err(at)?;
+                Ok(Flow::Normal)
+            }
+            Statement::Call { name, args, .. } => {
+                self.call(name, args).map_err(at)?;
+                Ok(Flow::Normal)
+            }
+            Statement::Return { value, .. } => match value {
+                Some(value) => Ok(Flow::Return(Some(self.evaluate_expression(value).map_err(at)?))),
+                None => Ok(Flow::Return(None)),
             },
-            Statement::Output { expressions } => {
-                // For output statements, just evaluate expressions
-                for expr in expressions {
-                    let _ = self.evaluate_expression(expr)?;
+            Statement::Break { .. } => Ok(Flow::Break),
+            Statement::Continue { .. } => Ok(Flow::Continue),
+            Statement::Error { .. } => Err(at("Cannot execute a statement with syntax errors".to_string())),
+        }
+    }
+
+    fn execute_assignment(&mut self, target: &Variable, value: &Expression) -> Result<(), String> {
+        let evaluated = self.evaluate_expression(value)?;
+
+        match target {
+            Variable::Simple(name, _) => {
+                let evaluated = self.coerce(name, evaluated)?;
+                self.symbol_table.update_value(name, evaluated)
+            }
+            Variable::Array { name, indices, .. } => {
+                let indices = self.evaluate_indices(name, indices)?;
+                let evaluated = self.coerce(name, evaluated)?;
+                self.symbol_table.update_array_element(name, &indices, evaluated)
+            }
+        }
+    }
+
+    fn execute_input(&mut self, var: &str) -> Result<(), String> {
+        let mut line = String::new();
+        self.output
+            .flush()
+            .map_err(|e| format!("Cannot write output: {}", e))?;
+        let read = self
+            .input
+            .read_line(&mut line)
+            .map_err(|e| format!("Cannot read input: {}", e))?;
+        if read == 0 {
+            return Err(format!("Unexpected end of input while reading '{}'", var));
+        }
+
+        let text = line.trim();
+        let data_type = match self.symbol_table.lookup(var) {
+            Some(entry) => entry.data_type.clone(),
+            None => return Err(format!("Undefined variable '{}'", var)),
+        };
+        let value = match data_type {
+            DataType::Int => text.parse().map(Value::Int).ok(),
+            DataType::Float => text.parse().map(Value::Float).ok(),
+            DataType::Bool => text.parse().map(Value::Bool).ok(),
+            // A string keeps the spaces around it, only the line ending is dropped
+            DataType::String => Some(Value::String(
+                line.trim_end_matches(['\r', '\n']).to_string(),
+            )),
+            DataType::Void => None,
+        };
+
+        match value {
+            Some(value) => self.symbol_table.update_value(var, value),
+            None => Err(format!(
+                "Invalid input '{}' for variable '{}' of type {:?}",
+                text, var, data_type
+            )),
+        }
+    }
+
+    fn execute_output(&mut self, expressions: &[Expression]) -> Result<(), String> {
+        let mut parts = Vec::new();
+        for expr in expressions {
+            parts.push(format_value(&self.evaluate_expression(expr)?));
+        }
+        writeln!(self.output, "{}", parts.join(" "))
+            .map_err(|e| format!("Cannot write output: {}", e))
+    }
+
+    // Runs a function in a fresh frame and returns its result, or None for a procedure
+    fn call(&mut self, name: &str, args: &[Expression]) -> Result<Option<Value>, String> {
+        // Builtins can be hidden by a function with the same name
+        if name == BUILTIN_LEN && !self.functions.contains_key(name) {
+            return match args {
+                [arg] => match self.evaluate_expression(arg)? {
+                    Value::String(s) => Ok(Some(Value::Int(s.chars().count() as i32))),
+                    _ => Err("'len' expects a String".to_string()),
+                },
+                _ => Err(format!("'len' takes 1 argument but {} were supplied", args.len())),
+            };
+        }
+
+        let (params, scope, return_type) = match self.symbol_table.lookup(name) {
+            Some(SymbolEntry {
+                entity_type: EntityType::Function { params, scope },
+                data_type,
+                ..
+            }) => (params.clone(), *scope, data_type.clone()),
+            _ => return Err(format!("'{}' is not a function", name)),
+        };
+        let function = match self.functions.get(name) {
+            Some(function) => Rc::clone(function),
+            None => return Err(format!("Function '{}' has no body", name)),
+        };
+        let (declarations, body) = &*function;
+
+        // Arguments are evaluated in the caller's frame
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.evaluate_expression(arg)?);
+        }
+
+        if self.call_depth == MAX_CALL_DEPTH {
+            return Err(format!(
+                "Too many nested calls while calling '{}', the limit is {}",
+                name, MAX_CALL_DEPTH
+            ));
+        }
+
+        // The function's scope holds the frame of the current call; the caller's
+        // frame is saved so that recursive calls get their own variables
+        let frame = self.initial[scope].clone();
+        let saved = std::mem::replace(&mut self.symbol_table.scopes[scope].symbols, frame);
+        let caller_scope = self.symbol_table.current_scope();
+        self.symbol_table.set_current_scope(scope);
+        self.call_depth += 1;
+
+        let bound = params.iter().zip(values).try_for_each(|((param, data_type), value)| {
+            let value = convert(data_type, value, param)?;
+            self.symbol_table.update_value(param, value)
+        });
+        let result = bound.and_then(|()| {
+            self.initialize(declarations)
+                .and_then(|()| self.execute_block(body))
+                .map_err(|err| {
+                    let message = err.message.clone();
+                    self.call_failure.get_or_insert(err);
+                    message
+                })
+        });
+
+        self.call_depth -= 1;
+        self.symbol_table.set_current_scope(caller_scope);
+        self.symbol_table.scopes[scope].symbols = saved;
+
+        match result? {
+            Flow::Return(Some(value)) => convert(&return_type, value, name).map(Some),
+            _ if return_type != DataType::Void => Err(format!(
+                "Function '{}' finished without returning a value",
+                name
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn evaluate_condition(&mut self, condition: &Condition) -> Result<bool, String> {
+        match condition {
+            Condition::Expr(expr) => {
+                let value = self.evaluate_expression(expr)?;
+                Ok(self.is_true(&value))
+            }
+        }
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expression) -> Result<Value, String> {
+        match expr {
+            Expression::Integer(n, _) => Ok(Value::Int(*n)),
+            Expression::Float(n, _) => Ok(Value::Float(*n)),
+            Expression::Bool(b, _) => Ok(Value::Bool(*b)),
+            Expression::String(s, _) => Ok(Value::String(s.clone())),
+            Expression::Literal(inner) => self.evaluate_expression(inner),
+            Expression::Var(var) => match var {
+                Variable::Simple(name, _) => self.read_variable(name),
+                Variable::Array { name, indices, .. } if self.is_string(name) => match indices.as_slice() {
+                    [index] => self.read_character(name, index),
+                    _ => Err(format!("String '{}' takes a single index", name)),
+                },
+                Variable::Array { name, indices, .. } => {
+                    let indices = self.evaluate_indices(name, indices)?;
+                    let mut element = match self.symbol_table.lookup(name) {
+                        Some(entry) => &entry.value,
+                        None => return Err(format!("Undefined variable '{}'", name)),
+                    };
+                    // Indices are within bounds, checked by `evaluate_indices`
+                    for &index in &indices {
+                        element = match element {
+                            Value::Array(elements) => &elements[index],
+                            _ => return Err(format!("'{}' is not an array", name)),
+                        };
+                    }
+                    match element {
+                        Value::Undefined => {
+                            let indices: Vec<String> = indices.iter().map(|index| format!("[{}]", index)).collect();
+                            Err(format!(
+                                "Array element '{}{}' used before assignment",
+                                name,
+                                indices.concat()
+                            ))
+                        }
+                        value => Ok(value.clone()),
+                    }
                 }
-                Ok(())
             },
-            Statement::IfElse { condition, if_block, else_block } => {
-                // Placeholder implementation for IfElse
-                // TODO: Implement full logic
-                Err(format!("IfElse statement not yet implemented"))
+            Expression::Binary { left, op, right, .. } => {
+                let left_val = self.evaluate_expression(left)?;
+
+                // AND and OR short-circuit on their left operand
+                match op {
+                    BinaryOp::And if !self.is_true(&left_val) => return Ok(Value::Bool(false)),
+                    BinaryOp::Or if self.is_true(&left_val) => return Ok(Value::Bool(true)),
+                    _ => {}
+                }
+
+                let right_val = self.evaluate_expression(right)?;
+                self.apply_binary(&left_val, op, &right_val)
+            }
+            Expression::Not(inner, _) => match self.evaluate_expression(inner)? {
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                _ => Err("Logical not requires a Bool operand".to_string()),
+            },
+            Expression::Negate(inner, _) => match self.evaluate_expression(inner)? {
+                Value::Int(i) => i
+                    .checked_neg()
+                    .map(Value::Int)
+                    .ok_or_else(|| format!("Integer overflow in -{}", i)),
+                Value::Float(f) => Ok(Value::Float(-f)),
+                _ => Err("Unary minus requires a numeric operand".to_string()),
             },
-            Statement::DoWhile { body, condition } => {
-                // Placeholder implementation for DoWhile
-                // TODO: Implement full logic
-                Err(format!("DoWhile statement not yet implemented"))
+            Expression::Plus(inner, _) => match self.evaluate_expression(inner)? {
+                value @ (Value::Int(_) | Value::Float(_)) => Ok(value),
+                _ => Err("Unary plus requires a numeric operand".to_string()),
             },
-            Statement::For { init, condition, update, body } => {
-                // Placeholder implementation for For
-                // TODO: Implement full logic
-                Err(format!("For statement not yet implemented"))
+            Expression::Call { name, args, .. } => match self.call(name, args)? {
+                Some(value) => Ok(value),
+                None => Err(format!("Procedure '{}' does not return a value", name)),
             },
-            _ => {
-                // Handle any other variant not explicitly covered
-                Err(format!("Unsupported statement type"))
+            Expression::ArrayLiteral { elements, .. } => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.evaluate_expression(element)?);
+                }
+                Ok(Value::Array(values))
+            }
+            Expression::Type(..) | Expression::ArrayType { .. } => {
+                Err("Type specifiers cannot be evaluated".to_string())
             }
         }
     }
-    
-    fn evaluate_expression(&self, expr: &Expression) -> Result<Value, String> {
-        match expr {
-            Expression::Literal(literal) => {
-                match literal {
-                    Literal::Int(i) => Ok(Value::Int(*i)),
-                    Literal::Float(f) => Ok(Value::Float(*f)),
-                    Literal::String(s) => Ok(Value::String(s.clone())),
-                    Literal::Boolean(b) => Ok(Value::Boolean(*b)),
-                }
+
+    fn is_string(&self, name: &str) -> bool {
+        match self.symbol_table.lookup(name) {
+            Some(entry) => entry.data_type == DataType::String && !matches!(entry.entity_type, EntityType::Array { .. }),
+            None => false,
+        }
+    }
+
+    // Indexing a string gives a string holding the character at that position
+    fn read_character(&mut self, name: &str, index: &Expression) -> Result<Value, String> {
+        let text = match self.read_variable(name)? {
+            Value::String(s) => s,
+            _ => return Err(format!("'{}' is not a string", name)),
+        };
+        let length = text.chars().count();
+
+        match self.evaluate_expression(index)? {
+            Value::Int(i) if i >= 0 && (i as usize) < length => {
+                Ok(Value::String(text.chars().nth(i as usize).unwrap().to_string()))
+            }
+            Value::Int(i) => Err(format!(
+                "String index out of bounds: '{}[{}]', length is {}",
+                name, i, length
+            )),
+            _ => Err("String index must be an Int".to_string()),
+        }
+    }
+
+    fn read_variable(&self, name: &str) -> Result<Value, String> {
+        match self.symbol_table.lookup(name) {
+            Some(entry) => match &entry.value {
+                Value::Undefined => Err(format!("Variable '{}' used before assignment", name)),
+                Value::Array(_) => Err(format!("Array '{}' used without an index", name)),
+                value => Ok(value.clone()),
             },
-            Expression::Var(var) => {
-                match var {
-                    Variable::Simple(name) => {
-                        if let Some(entry) = self.symbol_table.lookup(name) {
-                            Ok(entry.value.clone())
+            None => Err(format!("Undefined variable '{}'", name)),
+        }
+    }
+
+    // Evaluates the indices of an array element and checks each one against the
+    // size of its dimension
+    fn evaluate_indices(&mut self, name: &str, indices: &[Expression]) -> Result<Vec<usize>, String> {
+        let dimensions = match self.symbol_table.lookup(name) {
+            Some(entry) => match &entry.entity_type {
+                EntityType::Array { dimensions, .. } => dimensions.clone(),
+                _ => return Err(format!("'{}' is not an array", name)),
+            },
+            None => return Err(format!("Undefined variable '{}'", name)),
+        };
+        if indices.len() != dimensions.len() {
+            return Err(format!(
+                "'{}' has {} dimension(s) but {} index(es) were given",
+                name,
+                dimensions.len(),
+                indices.len()
+            ));
+        }
+
+        let mut evaluated = Vec::new();
+        for (dimension, (index, size)) in indices.iter().zip(&dimensions).enumerate() {
+            match self.evaluate_expression(index)? {
+                Value::Int(i) if i >= 0 && i < *size => evaluated.push(i as usize),
+                Value::Int(i) if dimensions.len() == 1 => {
+                    return Err(format!(
+                        "Array index out of bounds: '{}[{}]', size is {}",
+                        name, i, size
+                    ))
+                }
+                Value::Int(i) => {
+                    return Err(format!(
+                        "Array index out of bounds: {} in dimension {} of '{}', size is {}",
+                        i,
+                        dimension + 1,
+                        name,
+                        size
+                    ))
+                }
+                _ => return Err("Array index must be an Int".to_string()),
+            }
+        }
+        Ok(evaluated)
+    }
+
+    // Converts a value to the declared type of the variable it is stored in
+    fn coerce(&self, name: &str, value: Value) -> Result<Value, String> {
+        let data_type = match self.symbol_table.lookup(name) {
+            Some(entry) => entry.data_type.clone(),
+            None => return Err(format!("Undefined variable '{}'", name)),
+        };
+
+        convert(&data_type, value, name)
+    }
+
+    fn is_true(&self, value: &Value) -> bool {
+        matches!(value, Value::Bool(true))
+    }
+
+    fn apply_binary(&self, left: &Value, op: &BinaryOp, right: &Value) -> Result<Value, String> {
+        let bool_value = Value::Bool;
+
+        match op {
+            BinaryOp::And => return Ok(bool_value(self.is_true(left) && self.is_true(right))),
+            BinaryOp::Or => return Ok(bool_value(self.is_true(left) || self.is_true(right))),
+            _ => {}
+        }
+
+        match (left, right) {
+            (Value::Int(l), Value::Int(r)) => {
+                let (l, r) = (*l, *r);
+                let overflow = || format!("Integer overflow in {} {:?} {}", l, op, r);
+                match op {
+                    BinaryOp::Add => l.checked_add(r).map(Value::Int).ok_or_else(overflow),
+                    BinaryOp::Subtract => l.checked_sub(r).map(Value::Int).ok_or_else(overflow),
+                    BinaryOp::Multiply => l.checked_mul(r).map(Value::Int).ok_or_else(overflow),
+                    BinaryOp::Divide | BinaryOp::IntDivide | BinaryOp::Modulo => {
+                        if r == 0 {
+                            Err("Division by zero".to_string())
+                        } else if let BinaryOp::Modulo = op {
+                            l.checked_rem(r).map(Value::Int).ok_or_else(overflow)
                         } else {
-                            Err(format!("Undefined variable '{}'", name))
+                            l.checked_div(r).map(Value::Int).ok_or_else(overflow)
                         }
+                    }
+                    BinaryOp::Power => match u32::try_from(r) {
+                        Ok(exponent) => l.checked_pow(exponent).map(Value::Int).ok_or_else(overflow),
+                        Err(_) => Err(format!("Cannot raise the Int {} to the negative power {}", l, r)),
                     },
-                    // Handle array access
-                    Variable::Indexed { name, index } => {
-                        // Similar to array element updates
-                        let idx_value = self.evaluate_expression(index)?;
-                        // Implementation depends on your symbol table structure
-                        // ...
-                        Ok(Value::Int(0)) // Placeholder
+                    BinaryOp::LessThan => Ok(bool_value(l < r)),
+                    BinaryOp::GreaterThan => Ok(bool_value(l > r)),
+                    BinaryOp::LessEqual => Ok(bool_value(l <= r)),
+                    BinaryOp::GreaterEqual => Ok(bool_value(l >= r)),
+                    BinaryOp::Equal => Ok(bool_value(l == r)),
+                    BinaryOp::NotEqual => Ok(bool_value(l != r)),
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+            }
+            (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+                // Mixed operands are widened to Float
+                let l = as_float(left);
+                let r = as_float(right);
+                match op {
+                    BinaryOp::Add => Ok(Value::Float(l + r)),
+                    BinaryOp::Subtract => Ok(Value::Float(l - r)),
+                    BinaryOp::Multiply => Ok(Value::Float(l * r)),
+                    BinaryOp::Divide => {
+                        if r == 0.0 {
+                            Err("Division by zero".to_string())
+                        } else {
+                            Ok(Value::Float(l / r))
+                        }
                     }
+                    BinaryOp::Power => Ok(Value::Float(l.powf(r))),
+                    BinaryOp::Modulo | BinaryOp::IntDivide => {
+                        Err(format!("Unsupported operation {:?} between Float values", op))
+                    }
+                    BinaryOp::LessThan => Ok(bool_value(l < r)),
+                    BinaryOp::GreaterThan => Ok(bool_value(l > r)),
+                    BinaryOp::LessEqual => Ok(bool_value(l <= r)),
+                    BinaryOp::GreaterEqual => Ok(bool_value(l >= r)),
+                    BinaryOp::Equal => Ok(bool_value(l == r)),
+                    BinaryOp::NotEqual => Ok(bool_value(l != r)),
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
                 }
+            }
+            (Value::String(l), Value::String(r)) => match op {
+                BinaryOp::Add => Ok(Value::String(format!("{}{}", l, r))),
+                BinaryOp::Equal => Ok(bool_value(l == r)),
+                BinaryOp::NotEqual => Ok(bool_value(l != r)),
+                _ => Err(format!("Unsupported operation {:?} between String values", op)),
             },
-            Expression::Binary { left, op, right } => {
-                let left_val = self.evaluate_expression(left)?;
-                let right_val = self.evaluate_expression(right)?;
-                
-                match (left_val, op, right_val) {
-                    (Value::Int(l), BinaryOp::Add, Value::Int(r)) => Ok(Value::Int(l + r)),
-                    (Value::Int(l), BinaryOp::Multiply, Value::Int(r)) => Ok(Value::Int(l * r)),
-                    
-                    (Value::Float(l), BinaryOp::Add, Value::Float(r)) => Ok(Value::Float(l + r)),
-                    (Value::Float(l), BinaryOp::Multiply, Value::Float(r)) => Ok(Value::Float(l * r)),
-                    
-                    // Mixed type operations
-                    (Value::Int(l), BinaryOp::Add, Value::Float(r)) => Ok(Value::Float(l as f64 + r)),
-                    (Value::Float(l), BinaryOp::Add, Value::Int(r)) => Ok(Value::Float(l + r as f64)),
-                    (Value::Int(l), BinaryOp::Multiply, Value::Float(r)) => Ok(Value::Float(l as f64 * r)),
-                    (Value::Float(l), BinaryOp::Multiply, Value::Int(r)) => Ok(Value::Float(l * r as f64)),
-                    
-                    // Add more operations as needed
-                    _ => Err(format!("Unsupported operation between values"))
-                }
+            (Value::Bool(l), Value::Bool(r)) => match op {
+                BinaryOp::Equal => Ok(bool_value(l == r)),
+                BinaryOp::NotEqual => Ok(bool_value(l != r)),
+                _ => Err(format!("Unsupported operation {:?} between Bool values", op)),
             },
-            // Handle other expression types
+            _ => Err(format!("Unsupported operation {:?} between values", op)),
         }
     }
-}
\ No newline at end of file
+}
+
+// Converts a value to the type of the variable, parameter or function result `name`
+fn convert(data_type: &DataType, value: Value, name: &str) -> Result<Value, String> {
+    match (data_type, value) {
+        (DataType::Float, Value::Int(i)) => Ok(Value::Float(i as f32)),
+        (DataType::Int, Value::Float(f)) => Err(format!(
+            "Cannot store Float value {} in Int variable '{}'",
+            f, name
+        )),
+        (_, value) => Ok(value),
+    }
+}
+
+fn as_float(value: &Value) -> f32 {
+    match value {
+        Value::Int(i) => *i as f32,
+        Value::Float(f) => *f,
+        _ => 0.0,
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(format_value).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Value::Undefined => "-".to_string(),
+    }
+}
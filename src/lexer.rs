@@ -1,7 +1,28 @@
+use crate::diagnostics::{Diagnostic, ErrorCode, Label};
 use logos::{Logos, Span};
 use std::fmt;
 
+// MiniSoft identifiers are at most this many characters long
+pub const MAX_IDENTIFIER_LENGTH: usize = 14;
+
+// Why a piece of input could not be turned into a token
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LexErrorKind {
+    #[default]
+    InvalidCharacter,
+    IdentifierStartsWithDigit,
+    ConsecutiveUnderscores,
+    TrailingUnderscore,
+    IdentifierTooLong,
+    IntegerOverflow,
+    UnterminatedString,
+    // Position of the escape sequence, relative to the opening quote
+    UnknownEscape { offset: usize, len: usize },
+    InvalidUnicodeEscape { offset: usize, len: usize },
+}
+
 #[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(error = LexErrorKind)]
 pub enum Token {
     // Keywords
     #[token("MainPrgm")]
@@ -25,6 +46,18 @@ pub enum Token {
     #[token("Float")]
     Float,
 
+    #[token("Bool")]
+    Bool,
+
+    #[token("String")]
+    String,
+
+    #[token("true")]
+    True,
+
+    #[token("false")]
+    False,
+
     #[token("@define")]
     Define,
 
@@ -64,6 +97,27 @@ pub enum Token {
     #[token("step")]
     Step,
 
+    #[token("function")]
+    Function,
+
+    #[token("return")]
+    Return,
+
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("switch")]
+    Switch,
+
+    #[token("case")]
+    Case,
+
+    #[token("default")]
+    Default,
+
     // Operators
     #[token("+")]
     Plus,
@@ -80,6 +134,18 @@ pub enum Token {
     #[token("/")]
     Divide,
 
+    #[token("%")]
+    Percent,
+
+    #[token("MOD")]
+    Mod,
+
+    #[token("DIV")]
+    Div,
+
+    #[token("**")]
+    Power,
+
     #[token("<")]
     LessThan,
 
@@ -139,22 +205,21 @@ pub enum Token {
     RParen,
 
     // Literals
-    #[regex(r"[a-zA-Z]([a-zA-Z0-9_]*[a-zA-Z0-9])?", lex_identifier)]
+    // Malformed identifiers are matched as a whole so that they are reported
+    // once instead of being split into several valid tokens
+    #[regex(r"[a-zA-Z][a-zA-Z0-9_]*", lex_identifier)]
+    #[regex(r"[0-9]+[a-zA-Z_][a-zA-Z0-9_]*", |_| Err(LexErrorKind::IdentifierStartsWithDigit))]
     Identifier(String),
 
     #[regex(r"[0-9]+", lex_integer)]
     IntLiteral(i32),
 
-    #[regex(r"\([+-][0-9]+\)", lex_signed_integer)]
-    SignedIntLiteral(i32),
-
     #[regex(r"[0-9]+\.[0-9]+", lex_float)]
     FloatLiteral(f32),
 
-    #[regex(r"\([+-][0-9]+\.[0-9]+\)", lex_signed_float)]
-    SignedFloatLiteral(f32),
-
-    #[regex(r#""[^"]*""#, lex_string)]
+    // A string literal ends on the line it starts; a lone `"` is an unterminated one
+    #[regex(r#""([^"\\\n]|\\[^\n])*""#, lex_string)]
+    #[regex(r#""([^"\\\n]|\\[^\n])*\\?"#, |_| Err(LexErrorKind::UnterminatedString))]
     StringLiteral(String),
 
      // Comments - fixed regex patterns
@@ -168,36 +233,75 @@ pub enum Token {
 }
 
 // Helper functions for token conversion
-fn lex_identifier(lex: &mut logos::Lexer<Token>) -> String {
-    lex.slice().to_string()
-}
-
-fn lex_integer(lex: &mut logos::Lexer<Token>) -> i32 {
-    lex.slice().parse().unwrap_or(0)
+fn lex_identifier(lex: &mut logos::Lexer<Token>) -> Result<String, LexErrorKind> {
+    let text = lex.slice();
+    if text.contains("__") {
+        Err(LexErrorKind::ConsecutiveUnderscores)
+    } else if text.ends_with('_') {
+        Err(LexErrorKind::TrailingUnderscore)
+    } else if text.len() > MAX_IDENTIFIER_LENGTH {
+        Err(LexErrorKind::IdentifierTooLong)
+    } else {
+        Ok(text.to_string())
+    }
 }
 
-fn lex_signed_integer(lex: &mut logos::Lexer<Token>) -> i32 {
-    let text = lex.slice();
-    // Extract the sign and number from the parentheses format: "(+123)" or "(-123)"
-    let number_str = &text[1..text.len() - 1]; // Remove the parentheses
-    number_str.parse().unwrap_or(0)
+fn lex_integer(lex: &mut logos::Lexer<Token>) -> Result<i32, LexErrorKind> {
+    lex.slice().parse().map_err(|_| LexErrorKind::IntegerOverflow)
 }
 
 fn lex_float(lex: &mut logos::Lexer<Token>) -> f32 {
     lex.slice().parse().unwrap_or(0.0)
 }
 
-fn lex_signed_float(lex: &mut logos::Lexer<Token>) -> f32 {
+// Removes the surrounding quotes and decodes the escape sequences
+fn lex_string(lex: &mut logos::Lexer<Token>) -> Result<String, LexErrorKind> {
     let text = lex.slice();
-    // Extract the sign and number from the parentheses format: "(+123.45)" or "(-123.45)"
-    let number_str = &text[1..text.len() - 1]; // Remove the parentheses
-    number_str.parse().unwrap_or(0.0)
-}
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1).peekable();
 
-fn lex_string(lex: &mut logos::Lexer<Token>) -> String {
-    let text = lex.slice();
-    // Remove the surrounding quotes
-    text[1..text.len() - 1].to_string()
+    while let Some((offset, c)) = chars.next() {
+        if c == '"' {
+            break; // The closing quote
+        }
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+
+        // The regex guarantees that a backslash is followed by a character
+        let (_, escaped) = chars.next().unwrap();
+        match escaped {
+            'n' => value.push('\n'),
+            't' => value.push('\t'),
+            '"' => value.push('"'),
+            '\\' => value.push('\\'),
+            'u' => {
+                let rest = &text[offset + 2..];
+                let close = rest.find('}').filter(|_| rest.starts_with('{'));
+                let len = close.map_or(2, |close| close + 3);
+                let code = close
+                    .map(|close| &rest[1..close])
+                    .filter(|digits| (1..=6).contains(&digits.len()))
+                    .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                    .and_then(char::from_u32);
+                match code {
+                    Some(code) => value.push(code),
+                    None => return Err(LexErrorKind::InvalidUnicodeEscape { offset, len }),
+                }
+                // Skip the braces and the digits
+                while chars.next_if(|&(i, _)| i < offset + len).is_some() {}
+            }
+            other => {
+                return Err(LexErrorKind::UnknownEscape {
+                    offset,
+                    len: 1 + other.len_utf8(),
+                })
+            }
+        }
+    }
+
+    Ok(value)
 }
 
 // Implement Display for Token to pretty-print tokens
@@ -206,10 +310,8 @@ impl fmt::Display for Token {
         match self {
             Token::Identifier(s) => write!(f, "Identifier({})", s),
             Token::IntLiteral(n) => write!(f, "IntLiteral({})", n),
-            Token::SignedIntLiteral(n) => write!(f, "SignedIntLiteral({})", n),
-            Token::FloatLiteral(n) => write!(f, "FloatLiteral({})", n),
-            Token::SignedFloatLiteral(n) => write!(f, "SignedFloatLiteral({})", n),
-            Token::StringLiteral(s) => write!(f, "StringLiteral({})", s),
+            Token::FloatLiteral(n) => write!(f, "FloatLiteral({:?})", n),
+            Token::StringLiteral(s) => write!(f, "StringLiteral({:?})", s),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -219,47 +321,228 @@ impl fmt::Display for Token {
 pub struct LexResult {
     pub token: Token,
     pub span: Span,
-    #[allow(dead_code)]
     pub line: usize,
-    #[allow(dead_code)]
     pub column: usize,
 }
 
-pub fn lex(input: &str) -> Vec<LexResult> {
-    let mut lexer = Token::lexer(input);
-    let mut tokens = Vec::new();
-    let mut line_starts = vec![0];
-    
-    // Build line starts index for column calculation
-    for (i, c) in input.char_indices() {
-        if c == '\n' {
-            line_starts.push(i + 1);
+#[derive(Debug, Clone)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub message: String,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl LexError {
+    fn new(kind: LexErrorKind, text: &str, span: Span, line: usize, column: usize) -> Self {
+        let message = match kind {
+            LexErrorKind::InvalidCharacter => format!("unexpected character sequence `{}`", text),
+            LexErrorKind::IdentifierStartsWithDigit => {
+                format!("identifier `{}` starts with a digit", text)
+            }
+            LexErrorKind::ConsecutiveUnderscores => {
+                format!("identifier `{}` contains consecutive underscores", text)
+            }
+            LexErrorKind::TrailingUnderscore => {
+                format!("identifier `{}` ends with an underscore", text)
+            }
+            LexErrorKind::IdentifierTooLong => format!(
+                "identifier `{}` is longer than {} characters",
+                text, MAX_IDENTIFIER_LENGTH
+            ),
+            LexErrorKind::IntegerOverflow => {
+                format!("integer literal `{}` is too large for an Int", text)
+            }
+            LexErrorKind::UnterminatedString => "unterminated string literal".to_string(),
+            LexErrorKind::UnknownEscape { offset, len } => {
+                format!("unknown escape sequence `{}`", &text[offset..offset + len])
+            }
+            LexErrorKind::InvalidUnicodeEscape { offset, len } => {
+                format!("invalid unicode escape `{}`", &text[offset..offset + len])
+            }
+        };
+        LexError {
+            kind,
+            message,
+            span,
+            line,
+            column,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let span = crate::ast::Span::new(self.span.start, self.span.end);
+        let diagnostic = Diagnostic::error(self.message.clone(), span);
+        let opening_quote = crate::ast::Span::new(self.span.start, self.span.start + 1);
+        let escape = |offset: usize, len: usize| {
+            let start = self.span.start + offset;
+            Diagnostic::error(self.message.clone(), crate::ast::Span::new(start, start + len))
+                .with_code(ErrorCode::InvalidEscape)
+                .with_secondary(vec![Label::new(opening_quote, "in the string literal starting here")])
+        };
+        match self.kind {
+            LexErrorKind::UnterminatedString => Diagnostic::error(self.message.clone(), opening_quote)
+                .with_code(ErrorCode::UnterminatedString)
+                .with_label("string literal starts here and is not closed on this line")
+                .with_note("a string literal ends on the line it starts; write `\\n` for a line break"),
+            LexErrorKind::UnknownEscape { offset, len } => escape(offset, len)
+                .with_label("unknown escape")
+                .with_note("the supported escapes are `\\n`, `\\t`, `\\\"`, `\\\\` and `\\u{...}`"),
+            LexErrorKind::InvalidUnicodeEscape { offset, len } => escape(offset, len)
+                .with_label("not a valid character code")
+                .with_note("write the code as 1 to 6 hexadecimal digits in braces, e.g. `\\u{e9}`"),
+            LexErrorKind::InvalidCharacter => diagnostic
+                .with_code(ErrorCode::InvalidCharacter)
+                .with_label("not part of any MiniSoft token"),
+            LexErrorKind::IntegerOverflow => diagnostic
+                .with_code(ErrorCode::IntegerOverflow)
+                .with_note(format!(
+                    "Int values range from {} to {}",
+                    i32::MIN,
+                    i32::MAX
+                )),
+            LexErrorKind::IdentifierTooLong => diagnostic
+                .with_code(ErrorCode::MalformedIdentifier)
+                .with_note(format!(
+                    "identifiers are limited to {} characters",
+                    MAX_IDENTIFIER_LENGTH
+                )),
+            _ => diagnostic.with_code(ErrorCode::MalformedIdentifier).with_note(
+                "identifiers start with a letter, and may contain single underscores \
+                 between letters and digits",
+            ),
+        }
+    }
+}
+
+// Start offset of every line, used to turn byte offsets into line and column numbers
+#[derive(Debug, Clone, Default)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(input: &str) -> Self {
+        let mut line_starts = vec![0];
+        for (i, c) in input.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
         }
+        LineIndex { line_starts }
+    }
+
+    // Byte offset at which a 1-based line starts
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line - 1]
     }
 
+    // Returns the 1-based line and column of a byte offset
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        (line, offset - self.line_starts[line - 1] + 1)
+    }
+}
+
+pub fn lex(input: &str) -> (Vec<LexResult>, Vec<LexError>) {
+    let mut lexer = Token::lexer(input);
+    let mut tokens = Vec::new();
+    let mut errors: Vec<LexError> = Vec::new();
+    let line_index = LineIndex::new(input);
+
     while let Some(token_result) = lexer.next() {
+        let span = lexer.span();
+        let (line, column) = line_index.line_column(span.start);
+
         match token_result {
             Ok(token) if token != Token::Error => {
-                let span = lexer.span();
-                
-                // Calculate line and column of the token
-                let mut l = 1;
-                while l < line_starts.len() && line_starts[l] <= span.start {
-                    l += 1;
-                }
-                let token_line = l;
-                let token_column = span.start - line_starts[l - 1] + 1;
-                
                 tokens.push(LexResult {
-                    token: token.clone(),
+                    token,
                     span,
-                    line: token_line,
-                    column: token_column,
+                    line,
+                    column,
                 });
             }
-            _ => {}
+            Ok(_) => {}
+            Err(LexErrorKind::InvalidCharacter) => {
+                // A run of bad characters is reported once
+                match errors.last_mut() {
+                    Some(previous)
+                        if previous.kind == LexErrorKind::InvalidCharacter
+                            && previous.span.end == span.start =>
+                    {
+                        let merged = previous.span.start..span.end;
+                        *previous = LexError::new(
+                            LexErrorKind::InvalidCharacter,
+                            &input[merged.clone()],
+                            merged,
+                            previous.line,
+                            previous.column,
+                        );
+                    }
+                    _ => errors.push(LexError::new(
+                        LexErrorKind::InvalidCharacter,
+                        lexer.slice(),
+                        span,
+                        line,
+                        column,
+                    )),
+                }
+            }
+            Err(LexErrorKind::UnterminatedString) => {
+                errors.push(LexError::new(
+                    LexErrorKind::UnterminatedString,
+                    lexer.slice(),
+                    span,
+                    line,
+                    column,
+                ));
+                // A string broken by a newline usually closes on the next line; lexing
+                // resumes after that quote so that it does not start another string
+                let rest = lexer.remainder();
+                if let Some(newline) = rest.find('\n') {
+                    let next_line = rest[newline + 1..].lines().next().unwrap_or("");
+                    if let Some(quote) = next_line.find('"') {
+                        lexer.bump(newline + 1 + quote + 1);
+                    }
+                }
+            }
+            Err(kind) => errors.push(LexError::new(kind, lexer.slice(), span, line, column)),
         }
     }
 
-    tokens
-}
\ No newline at end of file
+    (tokens, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lex, LexErrorKind, Token};
+
+    #[test]
+    fn newline_in_string_is_one_error() {
+        let (tokens, errors) = lex("s := \"first\nsecond\";\noutput(s);");
+        let kinds: Vec<&LexErrorKind> = errors.iter().map(|error| &error.kind).collect();
+        assert_eq!(kinds, [&LexErrorKind::UnterminatedString]);
+        // Lexing resumes after the quote that closes the string on the next line
+        let after: Vec<&Token> = tokens.iter().skip(2).map(|result| &result.token).collect();
+        assert_eq!(
+            after,
+            [
+                &Token::Semicolon,
+                &Token::Output,
+                &Token::LParen,
+                &Token::Identifier("s".to_string()),
+                &Token::RParen,
+                &Token::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_string_at_end_of_input() {
+        let (_, errors) = lex("s := \"open");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, LexErrorKind::UnterminatedString);
+    }
+}
@@ -0,0 +1,111 @@
+// src/lib.rs
+
+// The MiniSoft compiler as a library: `compile` runs every stage on a source
+// text and returns everything the stages produced, so that tools can embed the
+// compiler instead of running the command line driver.
+
+pub mod ast;
+pub mod cfg;
+pub mod constant_propagation;
+pub mod diagnostics;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod quadruple;
+pub mod semantic_analyzer;
+pub mod symbol_table;
+
+use crate::ast::{Program, Span};
+use crate::constant_propagation::ConstantFact;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{LexResult, LineIndex};
+use crate::quadruple::{Quadruple, QuadrupleGenerator};
+use crate::semantic_analyzer::{SemanticAnalyzer, Type};
+use crate::symbol_table::SymbolTable;
+use std::collections::HashMap;
+
+// First stage that reported an error; later stages still ran where they could
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Lexical,
+    Syntax,
+    Semantic,
+}
+
+pub struct CompilationResult {
+    pub tokens: Vec<LexResult>,
+    // None if there were lexical errors, or if the parser could not recover
+    pub program: Option<Program>,
+    pub symbol_table: SymbolTable,
+    pub expression_types: HashMap<Span, Type>,
+    pub constant_facts: Vec<ConstantFact>,
+    // Errors and warnings of every stage, in the order the stages ran
+    pub diagnostics: Vec<Diagnostic>,
+    // Quadruples of the program; only generated when compilation succeeded
+    pub quadruples: Vec<Quadruple>,
+    pub failed_stage: Option<Stage>,
+}
+
+impl CompilationResult {
+    pub fn succeeded(&self) -> bool {
+        self.failed_stage.is_none()
+    }
+}
+
+pub fn compile(source: &str) -> CompilationResult {
+    let (tokens, lex_errors) = lexer::lex(source);
+    let mut result = CompilationResult {
+        tokens,
+        program: None,
+        symbol_table: SymbolTable::new(),
+        expression_types: HashMap::new(),
+        constant_facts: Vec::new(),
+        diagnostics: lex_errors.iter().map(|error| error.to_diagnostic()).collect(),
+        quadruples: Vec::new(),
+        failed_stage: None,
+    };
+
+    // Tokens after a lexical error cannot be trusted, so parsing stops here
+    if !lex_errors.is_empty() {
+        result.failed_stage = Some(Stage::Lexical);
+        return result;
+    }
+
+    let parsed = parser::parse(source, &result.tokens);
+    result
+        .diagnostics
+        .extend(parsed.errors.iter().map(|error| error.to_diagnostic()));
+    if !parsed.errors.is_empty() {
+        result.failed_stage = Some(Stage::Syntax);
+    }
+
+    let program = match parsed.program {
+        Some(program) => program,
+        None => return result,
+    };
+
+    // Semantic analysis also runs on the parts of the program the parser recovered
+    let mut analyzer = SemanticAnalyzer::new();
+    let analysis = analyzer.analyze(&program, LineIndex::new(source));
+    result
+        .diagnostics
+        .extend(analyzer.warnings.iter().map(|warning| warning.to_diagnostic()));
+    if let Err(errors) = analysis {
+        result
+            .diagnostics
+            .extend(errors.iter().map(|error| error.to_diagnostic()));
+        result.failed_stage.get_or_insert(Stage::Semantic);
+    }
+    result.symbol_table = analyzer.symbol_table;
+    result.expression_types = analyzer.expression_types;
+    result.constant_facts = analyzer.constant_facts;
+
+    if result.succeeded() {
+        let mut generator = QuadrupleGenerator::new();
+        generator.generate_from_program(&program, &result.symbol_table);
+        result.quadruples = generator.quads;
+    }
+
+    result.program = Some(program);
+    result
+}
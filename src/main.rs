@@ -1,87 +1,273 @@
 // src/main.rs
 
-use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process;
+use std::thread;
 
+use minisoft::diagnostics::{Diagnostic, Renderer, Severity};
+use minisoft::interpreter::Interpreter;
+use minisoft::lexer::LineIndex;
+use minisoft::quadruple::format_quads;
+use minisoft::Stage;
 
-mod ast;
-mod lexer;
-mod parser;
-mod semantic_analyzer;
-mod symbol_table; // Add these new modules
+// Exit codes, so that scripts can tell the failing compilation stage apart
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_LEXICAL_ERROR: i32 = 1;
+const EXIT_SYNTAX_ERROR: i32 = 2;
+const EXIT_SEMANTIC_ERROR: i32 = 3;
+const EXIT_RUNTIME_ERROR: i32 = 4;
+const EXIT_USAGE: i32 = 64;
+const EXIT_IO_ERROR: i32 = 74;
 
-use crate::semantic_analyzer::SemanticAnalyzer;
+// The interpreter recurses for every nested call, statement and expression; a
+// debug build needs more stack than the main thread has to reach MAX_CALL_DEPTH
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
-fn main() {
-    let input = r#"
- MainPrgm SimpleTest;
-Var
-let a, b, c: Int;
-@define Const PI: Float = 3.14;
-
-let result: Int;
-
-BeginPg
-{
-    a := 10;
-    c := 20;
-    b := 2;
-    result := a / b;
-    output("The result is:", result);
+const USAGE: &str = "\
+Usage: Min_compiler <command> [options] [input]
+
+Commands:
+    check      Run all compilation stages and report errors
+    run        Compile and execute the program
+    tokens     Print the token stream
+    ast        Print the abstract syntax tree
+    symbols    Print the symbol table
+    quads      Print the intermediate code as numbered quadruples
+    constants  Print the variable reads whose value is known at compile time
+
+Options:
+    -o, --output <path>    Write the command output to <path> instead of stdout
+    -h, --help             Print this help message
+
+The input is read from stdin when no path (or '-') is given. The 'run'
+command reads the program's own input() values from stdin as well.
+
+Exit codes:
+    0     success
+    1     lexical error
+    2     syntax error
+    3     semantic error
+    4     runtime error
+    64    invalid command line
+    74    input/output error";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Check,
+    Run,
+    Tokens,
+    Ast,
+    Symbols,
+    Quads,
+    Constants,
+}
+
+struct Options {
+    command: Command,
+    input: Option<String>,
+    output: Option<String>,
 }
-EndPg;
-    "#;
-
-    let mut output_file = File::create("output.txt").expect("Unable to create output file");
-
-    // Parse the program
-    match parser::parse(input) {
-        Ok(program) => {
-            writeln!(output_file, "Successfully parsed program: {:?}", program)
-                .expect("Unable to write to file");
-
-            // Build source map for identifiers
-            let mut source_map = HashMap::new();
-            let tokens = lexer::lex(input);
-
-            for token in &tokens {
-                match &token.token {
-                    lexer::Token::Identifier(name) => {
-                        source_map.insert(name.clone(), (token.line, token.column));
-                    }
-                    _ => {}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+
+    let command = match args.next().map(String::as_str) {
+        Some("check") => Command::Check,
+        Some("run") => Command::Run,
+        Some("tokens") => Command::Tokens,
+        Some("ast") => Command::Ast,
+        Some("symbols") => Command::Symbols,
+        Some("quads") => Command::Quads,
+        Some("constants") => Command::Constants,
+        Some(other) => return Err(format!("Unknown command '{}'", other)),
+        None => return Err("Missing command".to_string()),
+    };
+
+    let mut input = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path.clone()),
+                None => return Err(format!("Option '{}' requires a path", arg)),
+            },
+            "-" => input = None,
+            flag if flag.starts_with('-') => return Err(format!("Unknown option '{}'", flag)),
+            path => {
+                if input.is_some() {
+                    return Err("Only one input file can be given".to_string());
                 }
+                input = Some(path.to_string());
             }
+        }
+    }
 
-            // Run semantic analysis
-            let mut analyzer = SemanticAnalyzer::new();
-            match analyzer.analyze(&program, source_map) {
-                Ok(_) => {
-                    writeln!(
-                        output_file,
-                        "Semantic analysis successful.\n\nSymbol Table:"
-                    )
-                    .expect("Unable to write to file");
-                    writeln!(output_file, "{}", analyzer.symbol_table.format_table())
-                        .expect("Unable to write to file");
-                }
-                Err(errors) => {
-                    writeln!(output_file, "Semantic errors:").expect("Unable to write to file");
-                    for error in errors {
-                        writeln!(
-                            output_file,
-                            "Line {}, Column {}: {}",
-                            error.line, error.column, error.message
-                        )
-                        .expect("Unable to write to file");
-                    }
-                }
+    Ok(Options {
+        command,
+        input,
+        output,
+    })
+}
+
+fn read_source(input: &Option<String>) -> io::Result<String> {
+    let mut source = String::new();
+    match input {
+        Some(path) => {
+            File::open(path)?.read_to_string(&mut source)?;
+        }
+        None => {
+            io::stdin().read_to_string(&mut source)?;
+        }
+    }
+    Ok(source)
+}
+
+fn open_output(output: &Option<String>) -> io::Result<Box<dyn Write>> {
+    match output {
+        Some(path) => Ok(Box::new(File::create(path)?)),
+        None => Ok(Box::new(io::stdout())),
+    }
+}
+
+// Runs the pipeline up to the stage the command needs and returns the exit code
+fn run(options: &Options, source: &str, out: &mut dyn Write) -> io::Result<i32> {
+    let file_name = options.input.as_deref().unwrap_or("<stdin>");
+    let color = io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let renderer = Renderer::new(file_name, source, color);
+
+    let result = minisoft::compile(source);
+
+    if result.failed_stage == Some(Stage::Lexical) {
+        return Ok(report(&renderer, &result.diagnostics, EXIT_LEXICAL_ERROR));
+    }
+
+    if options.command == Command::Tokens {
+        for token in &result.tokens {
+            writeln!(out, "{:>4}:{:<4} {}", token.line, token.column, token.token)?;
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+
+    // The tree is printed as long as it parsed, even if it does not type check
+    if options.command == Command::Ast && result.failed_stage != Some(Stage::Syntax) {
+        if let Some(program) = &result.program {
+            writeln!(out, "{:#?}", program)?;
+            return Ok(EXIT_SUCCESS);
+        }
+    }
+
+    let program = match (result.failed_stage, &result.program) {
+        (Some(Stage::Syntax), _) | (_, None) => {
+            return Ok(report(&renderer, &result.diagnostics, EXIT_SYNTAX_ERROR))
+        }
+        (Some(_), _) => return Ok(report(&renderer, &result.diagnostics, EXIT_SEMANTIC_ERROR)),
+        (None, Some(program)) => program,
+    };
+
+    // Only warnings are left at this point
+    for diagnostic in &result.diagnostics {
+        eprintln!("{}", renderer.render(diagnostic));
+    }
+
+    match options.command {
+        Command::Run => {
+            let stdin = io::stdin();
+            let mut interpreter = Interpreter::new(
+                result.symbol_table,
+                Box::new(stdin.lock()),
+                Box::new(&mut *out),
+            );
+            if let Err(err) = interpreter.execute(program) {
+                let diagnostic = Diagnostic::error(format!("runtime error: {}", err.message), err.span)
+                    .with_label("while executing this statement");
+                eprintln!("{}", renderer.render(&diagnostic));
+                return Ok(EXIT_RUNTIME_ERROR);
             }
         }
-        Err(err) => {
-            writeln!(output_file, "Error parsing program: {}", err)
-                .expect("Unable to write to file");
+        Command::Symbols => write!(out, "{}", result.symbol_table.format_table())?,
+        Command::Quads => write!(out, "{}", format_quads(&result.quadruples))?,
+        Command::Constants => {
+            let line_index = LineIndex::new(source);
+            for fact in &result.constant_facts {
+                let (line, column) = line_index.line_column(fact.span.start);
+                writeln!(
+                    out,
+                    "{} is constant {} at line {}, column {}",
+                    fact.name, fact.value, line, column
+                )?;
+            }
         }
+        _ => writeln!(out, "Program '{}' compiled successfully.", program.name)?,
+    }
+
+    Ok(EXIT_SUCCESS)
+}
+
+// Prints every diagnostic followed by a summary line, and returns `exit_code`
+fn report(renderer: &Renderer, diagnostics: &[Diagnostic], exit_code: i32) -> i32 {
+    for diagnostic in diagnostics {
+        eprintln!("{}", renderer.render(diagnostic));
     }
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    match errors {
+        1 => eprintln!("aborting due to 1 previous error"),
+        n => eprintln!("aborting due to {} previous errors", n),
+    }
+    exit_code
+}
+
+fn main() {
+    let code = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(start)
+        .and_then(|driver| driver.join().map_err(|_| io::Error::other("the compiler panicked")))
+        .unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            EXIT_IO_ERROR
+        });
+
+    process::exit(code);
+}
+
+// Runs the command line driver and returns the exit code
+fn start() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return EXIT_SUCCESS;
+    }
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    let source = match read_source(&options.input) {
+        Ok(source) => source,
+        Err(err) => {
+            let name = options.input.as_deref().unwrap_or("<stdin>");
+            eprintln!("error: cannot read '{}': {}", name, err);
+            return EXIT_IO_ERROR;
+        }
+    };
+
+    open_output(&options.output)
+        .and_then(|mut out| {
+            let code = run(&options, &source, &mut *out)?;
+            out.flush()?;
+            Ok(code)
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("error: cannot write output: {}", err);
+            EXIT_IO_ERROR
+        })
 }
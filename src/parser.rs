@@ -1,9 +1,10 @@
-use crate::ast::Program;
-use crate::lexer::{Token, lex};
-use lalrpop_util::lalrpop_mod;
+use crate::ast::{Program, Span};
+use crate::diagnostics::{Diagnostic, ErrorCode};
+use crate::lexer::{LexResult, LineIndex, Token};
+use lalrpop_util::{lalrpop_mod, ErrorRecovery, ParseError};
 
 // Generate the parser code from the grammar file
-lalrpop_mod!(grammar); // this will expand to include the parser code generated by LALRPOP
+lalrpop_mod!(#[allow(clippy::all)] grammar); // this will expand to include the parser code generated by LALRPOP
 
 pub struct TokenIterator {  // Remove the unused lifetime parameter
     tokens: Vec<(usize, Token, usize)>,
@@ -11,16 +12,15 @@ pub struct TokenIterator {  // Remove the unused lifetime parameter
 }
 
 impl TokenIterator {
-    pub fn new(input: &str) -> Self {
-        let lexed_tokens = lex(input);
+    pub fn new(lexed_tokens: &[LexResult]) -> Self {
         let tokens: Vec<_> = lexed_tokens
-            .into_iter()
+            .iter()
             .map(|result| {
-                let span = result.span;
-                (span.start, result.token, span.end)
+                let span = result.span.clone();
+                (span.start, result.token.clone(), span.end)
             })
             .collect();
-        
+
         TokenIterator {
             tokens,
             pos: 0,
@@ -42,11 +42,203 @@ impl Iterator for TokenIterator {
     }
 }
 
-pub fn parse(input: &str) -> Result<Program, String> {
-    let token_iterator = TokenIterator::new(input);
-    
-    match grammar::ProgramParser::new().parse(token_iterator) {
-        Ok(program) => Ok(program),
-        Err(err) => Err(format!("Parse error: {:?}", err)),
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<String>, // Human readable names of the tokens that would have been accepted
+    pub context: Option<String>, // Construct being parsed, e.g. "a `for` loop header"
+}
+
+impl SyntaxError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.message.clone(), self.span).with_code(self.code);
+        match &self.context {
+            Some(context) => diagnostic.with_note(format!("while parsing {}", context)),
+            None => diagnostic,
+        }
+    }
+}
+
+pub struct ParseOutput {
+    // Partial program in which unparsable declarations and statements are
+    // error nodes, or None if the parser could not recover at all
+    pub program: Option<Program>,
+    pub errors: Vec<SyntaxError>,
+}
+
+// Parses the tokens lexed from `input`; lexical errors are reported by the caller
+pub fn parse(input: &str, tokens: &[LexResult]) -> ParseOutput {
+    let token_iterator = TokenIterator::new(tokens);
+    let mut recovered: Vec<ErrorRecovery<usize, Token, String>> = Vec::new();
+
+    let result = grammar::ProgramParser::new().parse(&mut recovered, token_iterator);
+
+    let mut errors: Vec<SyntaxError> = recovered
+        .into_iter()
+        .map(|recovery| syntax_error(input, tokens, recovery.error))
+        .collect();
+
+    let program = match result {
+        Ok(program) => Some(program),
+        Err(err) => {
+            errors.push(syntax_error(input, tokens, err));
+            None
+        }
+    };
+
+    ParseOutput { program, errors }
+}
+
+fn syntax_error(
+    input: &str,
+    tokens: &[LexResult],
+    err: ParseError<usize, Token, String>,
+) -> SyntaxError {
+    let (code, message, span, expected) = match err {
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => {
+            let expected = describe_expected(&expected);
+            let found = describe_token(&token, &input[start..end]);
+            let message = if expected.is_empty() {
+                format!("unexpected {}", found)
+            } else {
+                format!("expected {}, found {}", join_alternatives(&expected), found)
+            };
+            (ErrorCode::UnexpectedToken, message, Span::new(start, end), expected)
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            let expected = describe_expected(&expected);
+            let message = if expected.is_empty() {
+                "unexpected end of input".to_string()
+            } else {
+                format!("expected {}, found end of input", join_alternatives(&expected))
+            };
+            (ErrorCode::UnexpectedEndOfInput, message, Span::new(location, location), expected)
+        }
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => {
+            let message = format!(
+                "unexpected {} after the end of the program",
+                describe_token(&token, &input[start..end])
+            );
+            (ErrorCode::UnexpectedToken, message, Span::new(start, end), Vec::new())
+        }
+        ParseError::InvalidToken { location } => (
+            ErrorCode::UnexpectedToken,
+            "invalid token".to_string(),
+            Span::new(location, location),
+            Vec::new(),
+        ),
+        ParseError::User { error } => (
+            ErrorCode::UnexpectedToken,
+            error,
+            Span::default(),
+            Vec::new(),
+        ),
+    };
+
+    let (line, column) = LineIndex::new(input).line_column(span.start);
+    SyntaxError {
+        code,
+        message,
+        span,
+        line,
+        column,
+        expected,
+        context: grammar_context(tokens, span.start),
+    }
+}
+
+// LALRPOP names terminals either by their quoted text ("\":=\"") or by their
+// declared name (Identifier); both are turned into what a user would write
+fn describe_expected(expected: &[String]) -> Vec<String> {
+    let mut described: Vec<String> = expected
+        .iter()
+        .map(|terminal| match terminal.as_str() {
+            "Identifier" => "identifier".to_string(),
+            "IntLiteral" => "integer literal".to_string(),
+            "FloatLiteral" => "float literal".to_string(),
+            "StringLiteral" => "string literal".to_string(),
+            quoted => format!("`{}`", quoted.trim_matches('"')),
+        })
+        .collect();
+
+    // Every token that can start an expression is summarized as "an expression"
+    if EXPRESSION_START.iter().all(|start| described.iter().any(|d| d == start)) {
+        described.retain(|d| !EXPRESSION_START.contains(&d.as_str()));
+        described.push("an expression".to_string());
+    }
+    described
+}
+
+const EXPRESSION_START: &[&str] = &[
+    "`!`",
+    "`(`",
+    "`+`",
+    "`-`",
+    "`false`",
+    "`true`",
+    "identifier",
+    "integer literal",
+    "float literal",
+    "string literal",
+];
+
+fn describe_token(token: &Token, text: &str) -> String {
+    match token {
+        Token::Identifier(_) => format!("identifier `{}`", text),
+        Token::IntLiteral(_) => format!("integer literal `{}`", text),
+        Token::FloatLiteral(_) => format!("float literal `{}`", text),
+        Token::StringLiteral(_) => format!("string literal {}", text),
+        _ => format!("`{}`", text),
     }
-}
\ No newline at end of file
+}
+
+// ["`a`", "`b`", "`c`"] becomes "`a`, `b` or `c`"
+fn join_alternatives(items: &[String]) -> String {
+    match items.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+// Finds the innermost construct that is still open at `offset`, by replaying
+// the keywords that start a construct and the tokens that finish one
+fn grammar_context(tokens: &[LexResult], offset: usize) -> Option<String> {
+    let mut context = None;
+
+    for token in tokens.iter().take_while(|token| token.span.start < offset) {
+        context = match token.token {
+            Token::MainPrgm => Some("the program header"),
+            Token::Let => Some("a variable declaration"),
+            Token::Define => Some("a constant declaration"),
+            Token::If => Some("an `if` condition"),
+            Token::Do => None,
+            Token::While => Some("a `while` condition"),
+            Token::For => Some("a `for` loop header"),
+            Token::Switch => Some("a `switch` statement"),
+            Token::Case => Some("a `case` label"),
+            Token::Default => Some("the `default` case"),
+            Token::Input => Some("an `input` statement"),
+            Token::Output => Some("an `output` statement"),
+            Token::Function => Some("a function declaration"),
+            Token::Return => Some("a `return` statement"),
+            Token::Break => Some("a `break` statement"),
+            Token::Continue => Some("a `continue` statement"),
+            Token::Assign => Some("an assignment"),
+            Token::Var | Token::BeginPg | Token::Semicolon | Token::LBrace | Token::RBrace => None,
+            Token::Else => Some("an `else` branch"),
+            _ => context,
+        };
+    }
+
+    context.map(str::to_string)
+}
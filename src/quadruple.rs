@@ -1,11 +1,18 @@
 // src/quadruple.rs
 
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write;
+
 #[derive(Debug, Clone)]
 pub enum Operator {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    IntDivide,
+    Power,
     Assign,
     LessThan,
     GreaterThan,
@@ -13,15 +20,23 @@ pub enum Operator {
     GreaterEqual,
     Equal,
     NotEqual,
-    And,
-    Or,
     Not,
+    Negate,
     Goto,
     IfTrue,
     IfFalse,
+    // Jumps to the goto numbered arg1, counting from 0, after the label in result
+    JumpTable,
     Label,
     Input,
     Output,
+    Param,
+    // Binds the argument numbered arg1, counting from 0, of the current call to result
+    Receive,
+    Call,
+    Return,
+    // Stops the program with the runtime error message in arg1
+    Error,
 }
 
 #[derive(Debug, Clone)]
@@ -32,6 +47,7 @@ pub enum Operand {
     Temp(usize),
     Label(usize),
     StringLiteral(String),
+    Function(String),
 }
 
 #[derive(Debug, Clone)]
@@ -42,10 +58,28 @@ pub struct Quadruple {
     pub result: Option<Operand>,
 }
 
+// Jump targets of a loop for `continue` and `break`; a label is only created
+// once a statement jumps to it
+#[derive(Default)]
+struct LoopLabels {
+    continue_label: Option<Operand>,
+    exit_label: Option<Operand>,
+}
+
 pub struct QuadrupleGenerator {
     pub quads: Vec<Quadruple>,
     pub temp_count: usize,
     pub label_count: usize,
+    arrays: HashMap<String, Vec<i32>>, // Dimensions of the arrays in scope, by name
+    constants: HashMap<String, crate::symbol_table::Value>, // Folded values of the constants in scope
+    variables: HashMap<String, String>, // Names the symbols in scope have in the quadruples
+    loops: Vec<LoopLabels>, // Enclosing loops, innermost last
+}
+
+impl Default for QuadrupleGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl QuadrupleGenerator {
@@ -54,6 +88,10 @@ impl QuadrupleGenerator {
             quads: Vec::new(),
             temp_count: 0,
             label_count: 0,
+            arrays: HashMap::new(),
+            constants: HashMap::new(),
+            variables: HashMap::new(),
+            loops: Vec::new(),
         }
     }
     
@@ -81,60 +119,238 @@ impl QuadrupleGenerator {
     // Here you would implement methods to generate quads for each AST node type
     // For example:
     
-    pub fn generate_from_program(&mut self, program: &crate::ast::Program) {
+    // Array dimensions and constant values are taken from the symbol table, where
+    // the semantic analyzer resolved them
+    pub fn generate_from_program(&mut self, program: &crate::ast::Program, symbol_table: &crate::symbol_table::SymbolTable) {
         // Generate quads for declarations
-        for decl in &program.declarations {
-            self.generate_from_declaration(decl);
-        }
+        self.enter_scope(&symbol_table.scopes[0]);
+        self.generate_declarations(&program.declarations);
         
         // Generate quads for statements
         for stmt in &program.statements {
             self.generate_from_statement(stmt);
         }
+        
+        // Functions are placed after the main program, which ends with a return
+        self.emit(Operator::Return, None, None, None);
+        for decl in &program.declarations {
+            if let crate::ast::Declaration::Function { name, params, return_type, declarations, body, .. } = decl {
+                let scope = match symbol_table.scopes[0].symbols.get(name) {
+                    Some(crate::symbol_table::SymbolEntry {
+                        entity_type: crate::symbol_table::EntityType::Function { scope, .. },
+                        ..
+                    }) => *scope,
+                    _ => continue,
+                };
+                
+                // Parameters and locals hide the global arrays and constants of the same name
+                let global_arrays = self.arrays.clone();
+                let global_constants = self.constants.clone();
+                let global_variables = self.variables.clone();
+                self.enter_scope(&symbol_table.scopes[scope]);
+                
+                // The arguments passed by `param` are bound to the parameters in order
+                self.emit(Operator::Label, None, None, Some(Operand::Function(name.clone())));
+                for (position, param) in params.iter().enumerate() {
+                    let position = Operand::Constant(position.to_string());
+                    self.emit(Operator::Receive, Some(position), None, Some(Operand::Variable(self.ir_name(&param.name))));
+                }
+                self.generate_declarations(declarations);
+                for stmt in body {
+                    self.generate_from_statement(stmt);
+                }
+                
+                // A function returns on every path, a procedure may also run off its end
+                if return_type.is_none() {
+                    self.emit(Operator::Return, None, None, None);
+                }
+                self.arrays = global_arrays;
+                self.constants = global_constants;
+                self.variables = global_variables;
+            }
+        }
+    }
+    
+    // Makes the arrays and constants declared in a scope visible; names declared
+    // in a function are qualified with it so that they differ from the globals
+    fn enter_scope(&mut self, scope: &crate::symbol_table::Scope) {
+        for entry in scope.symbols.values() {
+            let ir_name = match scope.parent {
+                Some(_) => format!("{}.{}", scope.name, entry.name),
+                None => entry.name.clone(),
+            };
+            self.variables.insert(entry.name.clone(), ir_name);
+            match &entry.entity_type {
+                crate::symbol_table::EntityType::Array { dimensions, .. } => {
+                    self.arrays.insert(entry.name.clone(), dimensions.clone());
+                },
+                _ => {
+                    self.arrays.remove(&entry.name);
+                },
+            }
+            match &entry.entity_type {
+                crate::symbol_table::EntityType::Constant
+                | crate::symbol_table::EntityType::Array { constant: true, .. } => {
+                    self.constants.insert(entry.name.clone(), entry.value.clone());
+                },
+                _ => {
+                    self.constants.remove(&entry.name);
+                },
+            }
+        }
+    }
+    
+    // Name of a variable, array or constant in the quadruples, e.g. `setter.x` for
+    // the local `x` of `setter`
+    fn ir_name(&self, name: &str) -> String {
+        self.variables.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+    
+    // Constants are folded at compile time, so they are all set before the variable
+    // initializers that may use them
+    fn generate_declarations(&mut self, declarations: &[crate::ast::Declaration]) {
+        for decl in declarations {
+            if let crate::ast::Declaration::ConstDecl { .. } = decl {
+                self.generate_from_declaration(decl);
+            }
+        }
+        for decl in declarations {
+            if let crate::ast::Declaration::VariableDecl { .. } = decl {
+                self.generate_from_declaration(decl);
+            }
+        }
     }
     
     fn generate_from_declaration(&mut self, decl: &crate::ast::Declaration) {
-        // Implementation would depend on how you want to handle declarations
-        // Generally, variables don't need quadruples, but initializations might
+        // Variables without an initializer don't need quadruples, constants are initialized once
+        match decl {
+            crate::ast::Declaration::ConstDecl { name, .. } => {
+                match self.constants.get(name).cloned() {
+                    Some(crate::symbol_table::Value::Array(elements)) => {
+                        for (offset, element) in flatten(&elements).into_iter().enumerate() {
+                            let target = Operand::ArrayElement(self.ir_name(name), Box::new(Operand::Constant(offset.to_string())));
+                            self.emit(Operator::Assign, Some(value_operand(element)), None, Some(target));
+                        }
+                    },
+                    Some(value) => {
+                        self.emit(Operator::Assign, Some(value_operand(&value)), None, Some(Operand::Variable(self.ir_name(name))));
+                    },
+                    None => {},
+                }
+            },
+            crate::ast::Declaration::VariableDecl { names, initializer, .. } => {
+                let is_array = names.first().is_some_and(|(name, ..)| self.arrays.contains_key(name));
+                if is_array {
+                    for (name, _, own) in names {
+                        if let Some(initializer) = own.as_ref().or(initializer.as_ref()) {
+                            self.generate_initializer(name, initializer, &mut 0);
+                        }
+                    }
+                } else {
+                    // A shared scalar initializer is evaluated once and copied to every
+                    // name without its own
+                    let shared = match initializer {
+                        Some(initializer) if names.iter().any(|(_, _, own)| own.is_none()) => self.generate_from_expression(initializer),
+                        _ => None,
+                    };
+                    for (name, _, own) in names {
+                        let value_result = match own {
+                            Some(own) => self.generate_from_expression(own),
+                            None if initializer.is_some() => shared.clone(),
+                            None => continue,
+                        };
+                        self.emit(Operator::Assign, value_result, None, Some(Operand::Variable(self.ir_name(name))));
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    
+    // Assigns the elements of an initializer list in row-major order, starting at `offset`
+    fn generate_initializer(&mut self, name: &str, initializer: &crate::ast::Expression, offset: &mut usize) {
+        match initializer {
+            crate::ast::Expression::ArrayLiteral { elements, .. } => {
+                for element in elements {
+                    self.generate_initializer(name, element, offset);
+                }
+            },
+            element => {
+                let value_result = self.generate_from_expression(element);
+                let target = Operand::ArrayElement(self.ir_name(name), Box::new(Operand::Constant(offset.to_string())));
+                self.emit(Operator::Assign, value_result, None, Some(target));
+                *offset += 1;
+            },
+        }
+    }
+    
+    // Computes the row-major offset of an element, e.g. `i * cols + j` for `m[i][j]`
+    fn generate_element(&mut self, name: &str, indices: &[crate::ast::Expression]) -> Operand {
+        let dimensions = self.arrays.get(name).cloned().unwrap_or_default();
+        let mut offset = self.generate_from_expression(&indices[0]).unwrap();
+        
+        for (index, size) in indices[1..].iter().zip(dimensions.iter().skip(1)) {
+            let scaled = self.new_temp();
+            self.emit(Operator::Multiply, Some(offset), Some(Operand::Constant(size.to_string())), Some(scaled.clone()));
+            
+            let index_result = self.generate_from_expression(index).unwrap();
+            let sum = self.new_temp();
+            self.emit(Operator::Add, Some(scaled), Some(index_result), Some(sum.clone()));
+            offset = sum;
+        }
+        
+        Operand::ArrayElement(self.ir_name(name), Box::new(offset))
+    }
+    
+    // Passes the arguments one by one, then calls the function; returns the temporary
+    // holding its value when the call is used as an expression
+    fn generate_call(&mut self, name: &str, args: &[crate::ast::Expression], has_result: bool) -> Option<Operand> {
+        let arg_results: Vec<Option<Operand>> = args.iter().map(|arg| self.generate_from_expression(arg)).collect();
+        for arg_result in arg_results {
+            self.emit(Operator::Param, arg_result, None, None);
+        }
+        
+        let result = if has_result { Some(self.new_temp()) } else { None };
+        let arg_count = Operand::Constant(args.len().to_string());
+        self.emit(Operator::Call, Some(Operand::Function(name.to_string())), Some(arg_count), result.clone());
+        result
     }
     
     fn generate_from_statement(&mut self, stmt: &crate::ast::Statement) {
         match stmt {
-            crate::ast::Statement::Assignment { target, value, location: _ } => {
+            crate::ast::Statement::Assignment { target, value, .. } => {
                 // Generate code for the expression
                 let expr_result = self.generate_from_expression(value);
                 
                 // Create the assignment quadruple
                 let target_operand = match target {
-                    crate::ast::Variable::Simple(name) => Some(Operand::Variable(name.clone())),
-                    crate::ast::Variable::Array { name, index, location: _ } => {
-                        let index_result = self.generate_from_expression(index);
-                        Some(Operand::ArrayElement(name.clone(), Box::new(index_result.unwrap())))
-                    }
+                    crate::ast::Variable::Simple(name, _) => Some(Operand::Variable(self.ir_name(name))),
+                    crate::ast::Variable::Array { name, indices, .. } => Some(self.generate_element(name, indices)),
                 };
                 
                 self.emit(Operator::Assign, expr_result, None, target_operand);
             },
-            crate::ast::Statement::IfElse { condition, if_branch, else_branch, location: _ } => {
-                // Generate code for if-else statement
-                let cond_result = self.generate_from_condition(condition);
-                let else_label = self.new_label();
+            crate::ast::Statement::IfChain { branches, else_branch, .. } => {
+                // Generate code for an if-else chain
                 let end_label = self.new_label();
                 
-                // If condition is false, go to else branch
-                self.emit(Operator::IfFalse, cond_result, None, Some(else_label.clone()));
-                
-                // Generate code for if branch
-                for stmt in if_branch {
-                    self.generate_from_statement(stmt);
+                for branch in branches {
+                    // If condition is false, go to the next branch
+                    let next_label = self.new_label();
+                    self.generate_from_condition(&branch.condition, false, &next_label);
+                    
+                    // Generate code for the branch
+                    for stmt in &branch.body {
+                        self.generate_from_statement(stmt);
+                    }
+                    
+                    // After the branch, jump to end
+                    self.emit(Operator::Goto, None, None, Some(end_label.clone()));
+                    
+                    // Next branch label
+                    self.emit(Operator::Label, None, None, Some(next_label));
                 }
                 
-                // After if branch, jump to end
-                self.emit(Operator::Goto, None, None, Some(end_label.clone()));
-                
-                // Else label
-                self.emit(Operator::Label, None, None, Some(else_label));
-                
                 // Generate code for else branch
                 for stmt in else_branch {
                     self.generate_from_statement(stmt);
@@ -143,55 +359,154 @@ impl QuadrupleGenerator {
                 // End label
                 self.emit(Operator::Label, None, None, Some(end_label));
             },
-            crate::ast::Statement::DoWhile { condition, body, location: _ } => {
-                let start_label = self.new_label();
+            crate::ast::Statement::Switch { subject, cases, default, .. } => {
+                // The subject is evaluated once; the semantic analyzer checked that the
+                // labels are distinct Int constants
+                let subject_result = self.generate_once(subject);
+                let constants = &self.constants;
+                let mut targets = Vec::new();
+                for (index, case) in cases.iter().enumerate() {
+                    for label in &case.labels {
+                        if let Some(crate::symbol_table::Value::Int(value)) =
+                            crate::constant_propagation::evaluate(label, &|name| constants.get(name).cloned())
+                        {
+                            targets.push((value, index));
+                        }
+                    }
+                }
+                
                 let end_label = self.new_label();
+                let case_labels: Vec<Operand> = cases.iter().map(|_| self.new_label()).collect();
+                let default_label = if default.is_some() { self.new_label() } else { end_label.clone() };
+                
+                // Dispatch to the case bodies
+                if is_dense(&targets) {
+                    self.generate_jump_table(subject_result, targets, &case_labels, &default_label);
+                } else {
+                    for (value, index) in targets {
+                        let cond_temp = self.new_temp();
+                        self.emit(Operator::Equal, subject_result.clone(), Some(Operand::Constant(value.to_string())), Some(cond_temp.clone()));
+                        self.emit(Operator::IfTrue, Some(cond_temp), None, Some(case_labels[index].clone()));
+                    }
+                    self.emit(Operator::Goto, None, None, Some(default_label.clone()));
+                }
+                
+                // Case bodies, each followed by a jump past the switch
+                for (case, label) in cases.iter().zip(case_labels) {
+                    self.emit(Operator::Label, None, None, Some(label));
+                    for stmt in &case.body {
+                        self.generate_from_statement(stmt);
+                    }
+                    self.emit(Operator::Goto, None, None, Some(end_label.clone()));
+                }
+                
+                if let Some(default) = default {
+                    self.emit(Operator::Label, None, None, Some(default_label));
+                    for stmt in default {
+                        self.generate_from_statement(stmt);
+                    }
+                }
+                
+                self.emit(Operator::Label, None, None, Some(end_label));
+            },
+            crate::ast::Statement::DoWhile { condition, body, .. } => {
+                let start_label = self.new_label();
                 
                 // Start label
                 self.emit(Operator::Label, None, None, Some(start_label.clone()));
                 
-                // Generate code for body
-                for stmt in body {
-                    self.generate_from_statement(stmt);
+                // Generate code for body; `continue` goes to the condition
+                let labels = self.generate_loop_body(body, LoopLabels::default());
+                if let Some(continue_label) = labels.continue_label {
+                    self.emit(Operator::Label, None, None, Some(continue_label));
                 }
                 
-                // Generate code for condition
-                let cond_result = self.generate_from_condition(condition);
-                
                 // If condition is true, go back to start
-                self.emit(Operator::IfTrue, cond_result, None, Some(start_label));
+                self.generate_from_condition(condition, true, &start_label);
+                if let Some(exit_label) = labels.exit_label {
+                    self.emit(Operator::Label, None, None, Some(exit_label));
+                }
+            },
+            crate::ast::Statement::While { condition, body, .. } => {
+                let start_label = self.new_label();
+                let end_label = self.new_label();
+                
+                // If condition is false, leave the loop
+                self.emit(Operator::Label, None, None, Some(start_label.clone()));
+                self.generate_from_condition(condition, false, &end_label);
+                
+                // Generate code for body, then test again
+                let labels = LoopLabels {
+                    continue_label: Some(start_label.clone()),
+                    exit_label: Some(end_label.clone()),
+                };
+                self.generate_loop_body(body, labels);
+                self.emit(Operator::Goto, None, None, Some(start_label));
+                
+                self.emit(Operator::Label, None, None, Some(end_label));
             },
-            crate::ast::Statement::For { var, start, end, step, body, location: _ } => {
-                // Generate code for for loop
-                let loop_var = Operand::Variable(var.clone());
-                let start_result = self.generate_from_expression(start);
-                let end_result = self.generate_from_expression(end);
-                let step_result = self.generate_from_expression(step);
+            crate::ast::Statement::For { var, start, end, step, body, .. } => {
+                // Generate code for for loop; start, end and step are evaluated once,
+                // in this order
+                let loop_var = Operand::Variable(self.ir_name(var));
+                let start_result = self.generate_once(start);
+                let end_result = self.generate_once(end);
+                let step_result = self.generate_once(step);
                 
                 let loop_start = self.new_label();
                 let loop_end = self.new_label();
                 
-                // Initialize loop variable
+                // The loop counts up for a positive step and down for a negative one;
+                // the sign of a step that is not constant is tested once, before the
+                // loop, and a zero step is a runtime error
+                let constants = &self.constants;
+                let step_value = crate::constant_propagation::evaluate(step, &|name| constants.get(name).cloned());
+                let counts_down = match step_value {
+                    Some(crate::symbol_table::Value::Int(n)) => Some(n < 0),
+                    Some(crate::symbol_table::Value::Float(f)) => Some(f < 0.0),
+                    _ => None,
+                };
+                let step_sign = if counts_down.is_none() {
+                    let zero_temp = self.new_temp();
+                    let nonzero_label = self.new_label();
+                    self.emit(Operator::Equal, step_result.clone(), Some(Operand::Constant("0".to_string())), Some(zero_temp.clone()));
+                    self.emit(Operator::IfFalse, Some(zero_temp), None, Some(nonzero_label.clone()));
+                    let message = format!("The step of the for loop over '{}' is zero", var);
+                    self.emit(Operator::Error, Some(Operand::StringLiteral(message)), None, None);
+                    self.emit(Operator::Label, None, None, Some(nonzero_label));
+                    
+                    let sign_temp = self.new_temp();
+                    self.emit(Operator::LessThan, step_result.clone(), Some(Operand::Constant("0".to_string())), Some(sign_temp.clone()));
+                    Some(sign_temp)
+                } else {
+                    None
+                };
+                
+                // Initialize loop variable; the body does not run when start is past the end
                 self.emit(Operator::Assign, start_result, None, Some(loop_var.clone()));
+                self.generate_loop_exit(&loop_var, end_result.clone(), counts_down, &step_sign, false, &loop_end);
                 
                 // Loop start label
                 self.emit(Operator::Label, None, None, Some(loop_start.clone()));
                 
-                // Check if loop variable > end
-                let cond_temp = self.new_temp();
-                self.emit(Operator::GreaterThan, Some(loop_var.clone()), end_result, Some(cond_temp.clone()));
-                
-                // If condition is true, exit loop
-                self.emit(Operator::IfTrue, Some(cond_temp), None, Some(loop_end.clone()));
-                
-                // Generate code for loop body
-                for stmt in body {
-                    self.generate_from_statement(stmt);
+                // Generate code for loop body; `continue` goes to the increment
+                let labels = LoopLabels {
+                    continue_label: None,
+                    exit_label: Some(loop_end.clone()),
+                };
+                let labels = self.generate_loop_body(body, labels);
+                if let Some(continue_label) = labels.continue_label {
+                    self.emit(Operator::Label, None, None, Some(continue_label));
                 }
                 
-                // Increment loop variable
+                // The next value is tested before it is assigned, so the loop variable
+                // keeps the last value the body ran with. Int arithmetic wraps, so a
+                // next value on the wrong side of the current one has passed the
+                // largest or smallest Int
                 let new_val = self.new_temp();
                 self.emit(Operator::Add, Some(loop_var.clone()), step_result, Some(new_val.clone()));
+                self.generate_loop_exit(&new_val, end_result, counts_down, &step_sign, false, &loop_end);
+                self.generate_loop_exit(&new_val, Some(loop_var.clone()), counts_down, &step_sign, true, &loop_end);
                 self.emit(Operator::Assign, Some(new_val), None, Some(loop_var));
                 
                 // Jump back to loop start
@@ -200,22 +515,173 @@ impl QuadrupleGenerator {
                 // Loop end label
                 self.emit(Operator::Label, None, None, Some(loop_end));
             },
-            crate::ast::Statement::Input { var, location: _ } => {
-                let var_operand = Operand::Variable(var.clone());
+            crate::ast::Statement::Input { var, .. } => {
+                let var_operand = Operand::Variable(self.ir_name(var));
                 self.emit(Operator::Input, None, None, Some(var_operand));
             },
-            crate::ast::Statement::Output { expressions, location: _ } => {
+            crate::ast::Statement::Output { expressions, .. } => {
                 for expr in expressions {
                     let result = self.generate_from_expression(expr);
                     self.emit(Operator::Output, result, None, None);
                 }
             },
+            crate::ast::Statement::Call { name, args, .. } => {
+                self.generate_call(name, args, false);
+            },
+            crate::ast::Statement::Return { value, .. } => {
+                let value_result = value.as_ref().and_then(|value| self.generate_from_expression(value));
+                self.emit(Operator::Return, value_result, None, None);
+            },
+            crate::ast::Statement::Break { .. } => {
+                let exit_label = self.loop_label(true);
+                self.emit(Operator::Goto, None, None, exit_label);
+            },
+            crate::ast::Statement::Continue { .. } => {
+                let continue_label = self.loop_label(false);
+                self.emit(Operator::Goto, None, None, continue_label);
+            },
+            // Programs with syntax errors are never translated
+            crate::ast::Statement::Error { .. } => {},
+        }
+    }
+    
+    // Jumps through a table holding one goto per value between the smallest and the
+    // largest label; values outside that range and holes go to the default
+    fn generate_jump_table(&mut self, subject: Option<Operand>, mut targets: Vec<(i32, usize)>, case_labels: &[Operand], default_label: &Operand) {
+        targets.sort();
+        let (min, max) = (targets[0].0, targets[targets.len() - 1].0);
+        
+        // Position of the subject in the table
+        let offset = if min == 0 {
+            subject
+        } else {
+            let offset_temp = self.new_temp();
+            self.emit(Operator::Subtract, subject, Some(Operand::Constant(min.to_string())), Some(offset_temp.clone()));
+            Some(offset_temp)
+        };
+        
+        let below_temp = self.new_temp();
+        self.emit(Operator::LessThan, offset.clone(), Some(Operand::Constant("0".to_string())), Some(below_temp.clone()));
+        self.emit(Operator::IfTrue, Some(below_temp), None, Some(default_label.clone()));
+        let above_temp = self.new_temp();
+        self.emit(Operator::GreaterThan, offset.clone(), Some(Operand::Constant((max - min).to_string())), Some(above_temp.clone()));
+        self.emit(Operator::IfTrue, Some(above_temp), None, Some(default_label.clone()));
+        
+        let table_label = self.new_label();
+        self.emit(Operator::JumpTable, offset, None, Some(table_label.clone()));
+        self.emit(Operator::Label, None, None, Some(table_label));
+        let mut targets = targets.into_iter().peekable();
+        for value in min..=max {
+            let target = match targets.next_if(|&(label, _)| label == value) {
+                Some((_, index)) => case_labels[index].clone(),
+                None => default_label.clone(),
+            };
+            self.emit(Operator::Goto, None, None, Some(target));
+        }
+    }
+    
+    // Jumps to `target` when the loop variable is beyond `end`: above it when counting
+    // up, below it when counting down
+    fn generate_past_end(&mut self, loop_var: &Operand, end: Option<Operand>, counts_down: bool, target: &Operand) {
+        let operator = if counts_down { Operator::LessThan } else { Operator::GreaterThan };
+        let cond_temp = self.new_temp();
+        self.emit(operator, Some(loop_var.clone()), end, Some(cond_temp.clone()));
+        self.emit(Operator::IfTrue, Some(cond_temp), None, Some(target.clone()));
+    }
+    
+    // Jumps to `target` when `value` is past `bound` in the direction the loop counts,
+    // or in the opposite one when `reverse` is set; the direction of a step that is
+    // not constant is tested at run time through the `step_sign` temporary
+    fn generate_loop_exit(&mut self, value: &Operand, bound: Option<Operand>, counts_down: Option<bool>, step_sign: &Option<Operand>, reverse: bool, target: &Operand) {
+        match counts_down {
+            Some(counts_down) => self.generate_past_end(value, bound, counts_down != reverse, target),
+            None => {
+                let down_label = self.new_label();
+                let done_label = self.new_label();
+                self.emit(Operator::IfTrue, step_sign.clone(), None, Some(down_label.clone()));
+                self.generate_past_end(value, bound.clone(), reverse, target);
+                self.emit(Operator::Goto, None, None, Some(done_label.clone()));
+                self.emit(Operator::Label, None, None, Some(down_label));
+                self.generate_past_end(value, bound, !reverse, target);
+                self.emit(Operator::Label, None, None, Some(done_label));
+            },
+        }
+    }
+    
+    // Evaluates an expression into an operand that later statements cannot change
+    fn generate_once(&mut self, expr: &crate::ast::Expression) -> Option<Operand> {
+        match self.generate_from_expression(expr) {
+            Some(operand @ (Operand::Variable(_) | Operand::ArrayElement(..))) => {
+                let temp = self.new_temp();
+                self.emit(Operator::Assign, Some(operand), None, Some(temp.clone()));
+                Some(temp)
+            },
+            result => result,
+        }
+    }
+    
+    // Generates a loop body with `labels` as the innermost loop and returns the
+    // labels its statements jumped to
+    fn generate_loop_body(&mut self, body: &[crate::ast::Statement], labels: LoopLabels) -> LoopLabels {
+        self.loops.push(labels);
+        for stmt in body {
+            self.generate_from_statement(stmt);
         }
+        self.loops.pop().unwrap_or_default()
     }
     
-    fn generate_from_condition(&mut self, condition: &crate::ast::Condition) -> Option<Operand> {
+    // Exit or continue label of the innermost loop, created on first use
+    fn loop_label(&mut self, exit: bool) -> Option<Operand> {
+        let index = self.loops.len().checked_sub(1)?;
+        let existing = if exit { &self.loops[index].exit_label } else { &self.loops[index].continue_label };
+        if let Some(label) = existing {
+            return Some(label.clone());
+        }
+        
+        let label = self.new_label();
+        let labels = &mut self.loops[index];
+        if exit {
+            labels.exit_label = Some(label.clone());
+        } else {
+            labels.continue_label = Some(label.clone());
+        }
+        Some(label)
+    }
+    
+    // Jumps to `target` when the condition evaluates to `jump_if`, falls through otherwise
+    fn generate_from_condition(&mut self, condition: &crate::ast::Condition, jump_if: bool, target: &Operand) {
         match condition {
-            crate::ast::Condition::Expr(expr) => self.generate_from_expression(expr),
+            crate::ast::Condition::Expr(expr) => self.generate_jump(expr, jump_if, target),
+        }
+    }
+    
+    // AND and OR are translated to jumps, so their right operand is only evaluated
+    // when the left one does not decide the result
+    fn generate_jump(&mut self, expr: &crate::ast::Expression, jump_if: bool, target: &Operand) {
+        match expr {
+            crate::ast::Expression::Binary { left, op: op @ (crate::ast::BinaryOp::And | crate::ast::BinaryOp::Or), right, .. } => {
+                // The value of the left operand that decides the whole expression
+                let decisive = matches!(op, crate::ast::BinaryOp::Or);
+                
+                if decisive == jump_if {
+                    // Either operand alone is enough to jump
+                    self.generate_jump(left, jump_if, target);
+                    self.generate_jump(right, jump_if, target);
+                } else {
+                    // A decisive left operand skips the right one without jumping
+                    let skip_label = self.new_label();
+                    self.generate_jump(left, decisive, &skip_label);
+                    self.generate_jump(right, jump_if, target);
+                    self.emit(Operator::Label, None, None, Some(skip_label));
+                }
+            },
+            crate::ast::Expression::Not(inner, _) => self.generate_jump(inner, !jump_if, target),
+            crate::ast::Expression::Literal(inner) => self.generate_jump(inner, jump_if, target),
+            _ => {
+                let result = self.generate_from_expression(expr);
+                let operator = if jump_if { Operator::IfTrue } else { Operator::IfFalse };
+                self.emit(operator, result, None, Some(target.clone()));
+            },
         }
     }
     
@@ -223,17 +689,30 @@ impl QuadrupleGenerator {
         match expr {
             crate::ast::Expression::Var(var) => {
                 match var {
-                    crate::ast::Variable::Simple(name) => Some(Operand::Variable(name.clone())),
-                    crate::ast::Variable::Array { name, index, location: _ } => {
-                        let index_result = self.generate_from_expression(index);
-                        Some(Operand::ArrayElement(name.clone(), Box::new(index_result.unwrap())))
-                    }
+                    crate::ast::Variable::Simple(name, _) => Some(Operand::Variable(self.ir_name(name))),
+                    crate::ast::Variable::Array { name, indices, .. } => Some(self.generate_element(name, indices)),
                 }
             },
-            crate::ast::Expression::Integer(n) => Some(Operand::Constant(n.to_string())),
-            crate::ast::Expression::Float(n) => Some(Operand::Constant(n.to_string())),
-            crate::ast::Expression::String(s) => Some(Operand::StringLiteral(s.clone())),
-            crate::ast::Expression::Binary { left, op, right, location: _ } => {
+            crate::ast::Expression::Literal(inner) => self.generate_from_expression(inner),
+            crate::ast::Expression::Integer(n, _) => Some(Operand::Constant(n.to_string())),
+            crate::ast::Expression::Float(n, _) => Some(Operand::Constant(format!("{:?}", n))),
+            crate::ast::Expression::Bool(b, _) => Some(Operand::Constant(b.to_string())),
+            crate::ast::Expression::Binary { op: crate::ast::BinaryOp::And | crate::ast::BinaryOp::Or, .. } => {
+                // The value of a logical expression is stored by the branch that decides it
+                let result = self.new_temp();
+                let false_label = self.new_label();
+                let end_label = self.new_label();
+                
+                self.generate_jump(expr, false, &false_label);
+                self.emit(Operator::Assign, Some(Operand::Constant("true".to_string())), None, Some(result.clone()));
+                self.emit(Operator::Goto, None, None, Some(end_label.clone()));
+                self.emit(Operator::Label, None, None, Some(false_label));
+                self.emit(Operator::Assign, Some(Operand::Constant("false".to_string())), None, Some(result.clone()));
+                self.emit(Operator::Label, None, None, Some(end_label));
+                Some(result)
+            },
+            crate::ast::Expression::String(s, _) => Some(Operand::StringLiteral(s.clone())),
+            crate::ast::Expression::Binary { left, op, right, .. } => {
                 let left_result = self.generate_from_expression(left).unwrap();
                 let right_result = self.generate_from_expression(right).unwrap();
                 let result = self.new_temp();
@@ -243,27 +722,239 @@ impl QuadrupleGenerator {
                     crate::ast::BinaryOp::Subtract => Operator::Subtract,
                     crate::ast::BinaryOp::Multiply => Operator::Multiply,
                     crate::ast::BinaryOp::Divide => Operator::Divide,
+                    crate::ast::BinaryOp::Modulo => Operator::Modulo,
+                    crate::ast::BinaryOp::IntDivide => Operator::IntDivide,
+                    crate::ast::BinaryOp::Power => Operator::Power,
                     crate::ast::BinaryOp::LessThan => Operator::LessThan,
                     crate::ast::BinaryOp::GreaterThan => Operator::GreaterThan,
                     crate::ast::BinaryOp::LessEqual => Operator::LessEqual,
                     crate::ast::BinaryOp::GreaterEqual => Operator::GreaterEqual,
                     crate::ast::BinaryOp::Equal => Operator::Equal,
                     crate::ast::BinaryOp::NotEqual => Operator::NotEqual,
-                    crate::ast::BinaryOp::And => Operator::And,
-                    crate::ast::BinaryOp::Or => Operator::Or,
+                    crate::ast::BinaryOp::And | crate::ast::BinaryOp::Or => unreachable!(),
                 };
                 
                 self.emit(operator, Some(left_result), Some(right_result), Some(result.clone()));
                 Some(result)
             },
-            crate::ast::Expression::Not(expr) => {
+            crate::ast::Expression::Not(expr, _) => {
                 let expr_result = self.generate_from_expression(expr).unwrap();
                 let result = self.new_temp();
                 
                 self.emit(Operator::Not, Some(expr_result), None, Some(result.clone()));
                 Some(result)
             },
-                _ => None
-            }
+            crate::ast::Expression::Negate(expr, _) => {
+                let expr_result = self.generate_from_expression(expr).unwrap();
+                let result = self.new_temp();
+                
+                self.emit(Operator::Negate, Some(expr_result), None, Some(result.clone()));
+                Some(result)
+            },
+            // Unary plus leaves its operand unchanged
+            crate::ast::Expression::Plus(expr, _) => self.generate_from_expression(expr),
+            crate::ast::Expression::Call { name, args, .. } => self.generate_call(name, args, true),
+            // Initializer lists are only generated by `generate_initializer`
+            crate::ast::Expression::Type(..)
+            | crate::ast::Expression::ArrayType { .. }
+            | crate::ast::Expression::ArrayLiteral { .. } => None,
+        }
+    }
+    
+    pub fn format_quads(&self) -> String {
+        format_quads(&self.quads)
+    }
+}
+
+// Operand holding a folded constant value
+fn value_operand(value: &crate::symbol_table::Value) -> Operand {
+    match value {
+        crate::symbol_table::Value::String(s) => Operand::StringLiteral(s.clone()),
+        value => Operand::Constant(value.to_string()),
+    }
+}
+
+// Elements of a possibly nested array in row-major order
+fn flatten(elements: &[crate::symbol_table::Value]) -> Vec<&crate::symbol_table::Value> {
+    elements
+        .iter()
+        .flat_map(|element| match element {
+            crate::symbol_table::Value::Array(inner) => flatten(inner),
+            element => vec![element],
+        })
+        .collect()
+}
+
+// One numbered quadruple per line, e.g. "   3: (+, a, b, T0)"
+pub fn format_quads(quads: &[Quadruple]) -> String {
+    let mut output = String::new();
+    for (i, quad) in quads.iter().enumerate() {
+        writeln!(output, "{:>4}: {}", i, quad).unwrap();
+    }
+    output
+}
+
+// A switch dispatches through a jump table once it has a few labels that cover
+// at least half of the values between the smallest and the largest
+fn is_dense(targets: &[(i32, usize)]) -> bool {
+    let values = targets.iter().map(|&(value, _)| value as i64);
+    match (values.clone().min(), values.max()) {
+        (Some(min), Some(max)) => targets.len() >= 3 && max - min < 2 * targets.len() as i64,
+        _ => false,
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Modulo => "%",
+            Operator::IntDivide => "div",
+            Operator::Power => "**",
+            Operator::Assign => ":=",
+            Operator::LessThan => "<",
+            Operator::GreaterThan => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Not => "!",
+            Operator::Negate => "neg",
+            Operator::Goto => "goto",
+            Operator::IfTrue => "iftrue",
+            Operator::IfFalse => "iffalse",
+            Operator::JumpTable => "jumptable",
+            Operator::Label => "label",
+            Operator::Input => "input",
+            Operator::Output => "output",
+            Operator::Param => "param",
+            Operator::Receive => "receive",
+            Operator::Call => "call",
+            Operator::Return => "return",
+            Operator::Error => "error",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Variable(name) => write!(f, "{}", name),
+            Operand::Constant(value) => write!(f, "{}", value),
+            Operand::ArrayElement(name, index) => write!(f, "{}[{}]", name, index),
+            Operand::Temp(n) => write!(f, "T{}", n),
+            Operand::Label(n) => write!(f, "L{}", n),
+            Operand::StringLiteral(s) => write!(f, "{:?}", s),
+            Operand::Function(name) => write!(f, "{}", name),
         }
-    }
\ No newline at end of file
+    }
+}
+
+impl fmt::Display for Quadruple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = |operand: &Option<Operand>| match operand {
+            Some(operand) => operand.to_string(),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "({}, {}, {}, {})",
+            self.operator,
+            operand(&self.arg1),
+            operand(&self.arg2),
+            operand(&self.result)
+        )
+    }
+}
+#[cfg(test)]
+mod tests {
+    // Quadruples generated for a program, one rendered quadruple per element
+    fn quads(source: &str) -> Vec<String> {
+        let result = crate::compile(source);
+        assert!(result.succeeded(), "{:?}", result.diagnostics);
+        result.quadruples.iter().map(|quad| quad.to_string()).collect()
+    }
+    
+    #[test]
+    fn dense_switch_uses_a_jump_table() {
+        let quads = quads(
+            "MainPrgm p; Var let x, y: Int;
+             BeginPg {
+               switch (x) {
+                 case 1: { y := 10; }
+                 case 2, 4: { y := 20; }
+                 default: { y := 0; }
+               }
+             } EndPg;",
+        );
+        assert_eq!(
+            quads[..12],
+            [
+                "(:=, x, , T0)",
+                "(-, T0, 1, T1)",
+                "(<, T1, 0, T2)",
+                "(iftrue, T2, , L3)",
+                "(>, T1, 3, T3)",
+                "(iftrue, T3, , L3)",
+                "(jumptable, T1, , L4)",
+                "(label, , , L4)",
+                "(goto, , , L1)",
+                "(goto, , , L2)",
+                // 3 has no case, so its entry jumps to the default
+                "(goto, , , L3)",
+                "(goto, , , L2)",
+            ]
+        );
+        assert_eq!(quads[18..20], ["(label, , , L3)", "(:=, 0, , y)"]);
+    }
+    
+    #[test]
+    fn sparse_switch_compares_each_label() {
+        let quads = quads(
+            "MainPrgm p; Var let x, y: Int;
+             BeginPg {
+               switch (x) {
+                 case 1: { y := 10; }
+                 case 100: { y := 20; }
+                 case 1000: { y := 30; }
+               }
+             } EndPg;",
+        );
+        assert_eq!(
+            quads[..8],
+            [
+                "(:=, x, , T0)",
+                "(==, T0, 1, T1)",
+                "(iftrue, T1, , L1)",
+                "(==, T0, 100, T2)",
+                "(iftrue, T2, , L2)",
+                "(==, T0, 1000, T3)",
+                "(iftrue, T3, , L3)",
+                // Without a default the switch is left when no label matches
+                "(goto, , , L0)",
+            ]
+        );
+        assert!(!quads.iter().any(|quad| quad.starts_with("(jumptable")));
+    }
+    
+    #[test]
+    fn and_or_jump_over_the_right_operand() {
+        let quads = quads(
+            "MainPrgm p; Var let x, y: Int; let b: Bool;
+             BeginPg {
+               b := (x > 0) AND (10 / y > 1);
+               if ((x == 0) OR (10 / y > 1)) then { output(2); }
+             } EndPg;",
+        );
+        // A false left operand of AND jumps to the false result
+        assert_eq!(quads[..3], ["(>, x, 0, T1)", "(iffalse, T1, , L0)", "(/, 10, y, T2)"]);
+        assert_eq!(quads[7..9], ["(label, , , L0)", "(:=, false, , T0)"]);
+        // A true left operand of OR jumps straight into the then branch
+        assert_eq!(quads[11..14], ["(==, x, 0, T4)", "(iftrue, T4, , L4)", "(/, 10, y, T5)"]);
+        assert_eq!(quads[16..18], ["(label, , , L4)", "(output, 2, , )"]);
+    }
+}
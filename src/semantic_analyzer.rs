@@ -1,20 +1,234 @@
 // src/semantic_analyzer.rs
 
-use crate::ast::{BinaryOp, Condition, Declaration, Expression, Program, Statement, Variable};
-use crate::symbol_table::{DataType, EntityType, SymbolEntry, SymbolTable, Value};
-use std::collections::HashMap;
+use crate::ast::{
+    BinaryOp, Condition, Declaration, Expression, Program, Span, Statement, Variable,
+};
+use crate::constant_propagation::{self, ConstantFact};
+use crate::diagnostics::{Diagnostic, ErrorCode, Label, Severity};
+use crate::lexer::LineIndex;
+use crate::symbol_table::{DataType, EntityType, SymbolEntry, SymbolTable, Value, BUILTIN_LEN};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+// Every element of an array is allocated when it is declared, so its total number
+// of elements is limited
+pub const MAX_ARRAY_ELEMENTS: usize = 1 << 20;
 
 #[derive(Debug, Clone)]
 pub struct SemanticError {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
     pub message: String,
+    pub span: Span,
     pub line: usize,
     pub column: usize,
+    pub labels: Vec<Label>, // Related locations, e.g. where a constant was declared
+}
+
+impl SemanticError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.severity, self.message.clone(), self.span)
+            .with_secondary(self.labels.clone());
+        match self.code {
+            Some(code) => diagnostic.with_code(code),
+            None => diagnostic,
+        }
+    }
+}
+
+// Static type of an expression.
+//
+// Int widens implicitly to Float wherever a Float is expected: when assigned to a
+// Float variable or element, and when mixed with a Float in arithmetic or a
+// comparison (the result is then Float). Float never narrows to Int implicitly.
+// Comparisons and logical operators yield Bool, which is what conditions require.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Void, // Result of calling a procedure
+    Array { element: DataType, dimensions: Vec<i32> },
+    Error, // Already reported, so that one mistake does not cascade
+}
+
+impl Type {
+    pub fn scalar(data_type: &DataType) -> Type {
+        match data_type {
+            DataType::Int => Type::Int,
+            DataType::Float => Type::Float,
+            DataType::Bool => Type::Bool,
+            DataType::String => Type::String,
+            DataType::Void => Type::Void,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    pub fn is_assignable_to(&self, target: &DataType) -> bool {
+        matches!(
+            (self, target),
+            (Type::Error, _)
+                | (Type::Int, DataType::Int | DataType::Float)
+                | (Type::Float, DataType::Float)
+                | (Type::Bool, DataType::Bool)
+                | (Type::String, DataType::String)
+        )
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Bool => write!(f, "Bool"),
+            Type::String => write!(f, "String"),
+            Type::Void => write!(f, "Void"),
+            Type::Array { element, dimensions } => {
+                let nested = dimensions
+                    .iter()
+                    .rev()
+                    .fold(format!("{:?}", element), |inner, size| format!("[{}; {}]", inner, size));
+                write!(f, "{}", nested)
+            }
+            Type::Error => write!(f, "{{unknown}}"),
+        }
+    }
+}
+
+// Result type of a binary operator, or None if the operands do not fit it.
+// Operands that are already erroneous make the result erroneous silently.
+fn binary_result_type(op: &BinaryOp, left: &Type, right: &Type) -> Option<Type> {
+    if *left == Type::Error || *right == Type::Error {
+        return Some(Type::Error);
+    }
+
+    match op {
+        BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Power => {
+            match (left, right) {
+                (Type::Int, Type::Int) => Some(Type::Int),
+                // `+` also concatenates strings
+                (Type::String, Type::String) if matches!(op, BinaryOp::Add) => Some(Type::String),
+                _ if left.is_numeric() && right.is_numeric() => Some(Type::Float),
+                _ => None,
+            }
+        }
+        BinaryOp::Modulo | BinaryOp::IntDivide => {
+            (*left == Type::Int && *right == Type::Int).then_some(Type::Int)
+        }
+        BinaryOp::LessThan | BinaryOp::GreaterThan | BinaryOp::LessEqual | BinaryOp::GreaterEqual => {
+            (left.is_numeric() && right.is_numeric()).then_some(Type::Bool)
+        }
+        BinaryOp::Equal | BinaryOp::NotEqual => {
+            let comparable = (left.is_numeric() && right.is_numeric())
+                || (*left == Type::Bool && *right == Type::Bool)
+                || (*left == Type::String && *right == Type::String);
+            comparable.then_some(Type::Bool)
+        }
+        BinaryOp::And | BinaryOp::Or => {
+            (*left == Type::Bool && *right == Type::Bool).then_some(Type::Bool)
+        }
+    }
+}
+
+// Maps a type name accepted by the grammar to its data type
+fn type_named(type_name: &str) -> DataType {
+    match type_name {
+        "Float" => DataType::Float,
+        "Bool" => DataType::Bool,
+        "String" => DataType::String,
+        _ => DataType::Int,
+    }
+}
+
+// Whether every path through the statements ends in a `return`
+fn always_returns(statements: &[Statement]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Statement::Return { .. } => true,
+        Statement::IfChain {
+            branches,
+            else_branch,
+            ..
+        } => branches.iter().all(|branch| always_returns(&branch.body)) && always_returns(else_branch),
+        // The body of a do-while runs at least once, unless a `break` leaves it early
+        Statement::DoWhile { body, .. } => always_returns(body) && !breaks_out(body),
+        // Without a default no case may run at all
+        Statement::Switch { cases, default: Some(default), .. } => {
+            cases.iter().all(|case| always_returns(&case.body)) && always_returns(default)
+        }
+        _ => false,
+    })
+}
+
+// Whether a `break` in the statements leaves the loop directly around them
+fn breaks_out(statements: &[Statement]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Statement::Break { .. } => true,
+        Statement::IfChain {
+            branches,
+            else_branch,
+            ..
+        } => branches.iter().any(|branch| breaks_out(&branch.body)) || breaks_out(else_branch),
+        // A `break` in a case leaves the loop around the switch
+        Statement::Switch { cases, default, .. } => {
+            cases.iter().any(|case| breaks_out(&case.body)) || default.as_deref().is_some_and(breaks_out)
+        }
+        // A nested loop catches its own breaks
+        _ => false,
+    })
+}
+
+fn operator_symbol(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Subtract => "-",
+        BinaryOp::Multiply => "*",
+        BinaryOp::Divide => "/",
+        BinaryOp::Modulo => "%",
+        BinaryOp::IntDivide => "DIV",
+        BinaryOp::Power => "**",
+        BinaryOp::LessThan => "<",
+        BinaryOp::GreaterThan => ">",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::GreaterEqual => ">=",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::And => "AND",
+        BinaryOp::Or => "OR",
+    }
+}
+
+// Why an expression that has to be constant could not be folded
+enum ConstantFailure {
+    Error(ErrorCode, String, Span),
+    Reported, // It uses a constant whose own definition was reported as wrong
 }
 
 pub struct SemanticAnalyzer {
     pub symbol_table: SymbolTable,
     pub errors: Vec<SemanticError>,
-    pub source_map: HashMap<String, (usize, usize)>, // Map identifiers to line and column
+    pub warnings: Vec<SemanticError>, // Reported alongside errors, but do not fail the analysis
+    pub expression_types: HashMap<Span, Type>, // Inferred type of every checked expression
+    pub constant_facts: Vec<ConstantFact>,      // Variable reads with a value known at compile time
+    pub line_index: LineIndex, // Turns node spans into line and column numbers
+    current_function: Option<(String, DataType)>, // Name and return type of the function being checked
+    loop_depth: usize, // Number of loops around the statement being checked
+    loop_variables: Vec<(String, Span)>, // Variables of the enclosing for loops, innermost last
+    // Constants of the declaration list being processed that are not declared yet
+    pending_constants: HashMap<String, Declaration>,
+    resolved_constants: HashSet<Span>, // Constant declarations already processed
+    evaluating: Vec<String>,           // Constants being declared, innermost last
+    cyclic_constants: HashSet<String>,
+}
+
+impl Default for SemanticAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SemanticAnalyzer {
@@ -22,27 +236,48 @@ impl SemanticAnalyzer {
         SemanticAnalyzer {
             symbol_table: SymbolTable::new(),
             errors: Vec::new(),
-            source_map: HashMap::new(),
+            warnings: Vec::new(),
+            expression_types: HashMap::new(),
+            constant_facts: Vec::new(),
+            line_index: LineIndex::default(),
+            current_function: None,
+            loop_depth: 0,
+            loop_variables: Vec::new(),
+            pending_constants: HashMap::new(),
+            resolved_constants: HashSet::new(),
+            evaluating: Vec::new(),
+            cyclic_constants: HashSet::new(),
         }
     }
 
     pub fn analyze(
         &mut self,
         program: &Program,
-        source_map: HashMap<String, (usize, usize)>,
+        line_index: LineIndex,
     ) -> Result<(), Vec<SemanticError>> {
-        self.source_map = source_map;
+        self.line_index = line_index;
 
         // Process declarations
-        for decl in &program.declarations {
-            self.process_declaration(decl);
-        }
+        self.process_declarations(&program.declarations);
 
         // Process statements
         for stmt in &program.statements {
             self.process_statement(stmt);
         }
 
+        // Facts that depend on control flow, such as a divisor that is always zero
+        let propagation = constant_propagation::analyze(program, &self.symbol_table);
+        for span in propagation.zero_divisors {
+            let reported = self
+                .errors
+                .iter()
+                .any(|e| e.code == Some(ErrorCode::DivisionByZero) && e.span == span);
+            if !reported {
+                self.report(ErrorCode::DivisionByZero, "Division by zero".to_string(), span);
+            }
+        }
+        self.constant_facts = propagation.facts;
+
         if self.errors.is_empty() {
             Ok(())
         } else {
@@ -50,739 +285,1362 @@ impl SemanticAnalyzer {
         }
     }
 
+    // Records an error at the position of the given node
+    fn report(&mut self, code: ErrorCode, message: String, span: Span) {
+        self.report_with_labels(code, message, span, Vec::new());
+    }
+
+    fn report_with_labels(
+        &mut self,
+        code: ErrorCode,
+        message: String,
+        span: Span,
+        labels: Vec<Label>,
+    ) {
+        let (line, column) = self.line_index.line_column(span.start);
+        self.errors.push(SemanticError {
+            severity: Severity::Error,
+            code: Some(code),
+            message,
+            span,
+            line,
+            column,
+            labels,
+        });
+    }
+
+    fn report_undeclared(&mut self, name: &str, span: Span) {
+        self.report(
+            ErrorCode::UndeclaredIdentifier,
+            format!("Undeclared identifier: '{}'", name),
+            span,
+        );
+    }
+
+    // Points at the declaration of a symbol, if it has one
+    fn declared_here(&self, name: &str, message: String) -> Vec<Label> {
+        match self.symbol_table.lookup(name) {
+            Some(entry) => vec![Label::new(entry.span, message)],
+            None => Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, message: String, span: Span, labels: Vec<Label>) {
+        let (line, column) = self.line_index.line_column(span.start);
+        self.warnings.push(SemanticError {
+            severity: Severity::Warning,
+            code: None,
+            message,
+            span,
+            line,
+            column,
+            labels,
+        });
+    }
+
+    fn declare(&mut self, entry: SymbolEntry) {
+        let name = entry.name.clone();
+        let span = entry.span;
+
+        // A declaration in the same scope is a duplicate, one in an outer scope is shadowed
+        if self.symbol_table.lookup_current(&name).is_none() {
+            if let Some(outer) = self.symbol_table.lookup(&name) {
+                let labels = vec![Label::new(outer.span, "outer declaration here")];
+                self.warn(
+                    format!("'{}' shadows a declaration from an outer scope", name),
+                    span,
+                    labels,
+                );
+            }
+        }
+
+        let labels = self.declared_here(&name, format!("'{}' first declared here", name));
+        if let Err(e) = self.symbol_table.insert(entry) {
+            self.report_with_labels(ErrorCode::DuplicateDeclaration, e, span, labels);
+        }
+    }
+
+    // Constants may be used before their declaration in the same list, as long as
+    // their definitions are not cyclic
+    fn process_declarations(&mut self, declarations: &[Declaration]) {
+        let mut pending = HashMap::new();
+        for decl in declarations {
+            if let Declaration::ConstDecl { name, .. } = decl {
+                pending.entry(name.clone()).or_insert_with(|| decl.clone());
+            }
+        }
+
+        let outer = std::mem::replace(&mut self.pending_constants, pending);
+        for decl in declarations {
+            self.process_declaration(decl);
+        }
+        self.pending_constants = outer;
+    }
+
     fn process_declaration(&mut self, decl: &Declaration) {
         match decl {
-            Declaration::VariableDecl { names, type_spec } => {
-                let data_type = self.get_data_type(type_spec);
-
-                for name in names {
-                    let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-
-                    match type_spec {
-                        Expression::Type(_) => {
-                            let entry = SymbolEntry {
-                                name: name.clone(),
-                                entity_type: EntityType::Variable,
-                                data_type: data_type.clone(),
-                                value: Value::Undefined,
-                                line,
-                                column,
-                            };
-
-                            if let Err(e) = self.symbol_table.insert(entry) {
-                                self.errors.push(SemanticError {
-                                    message: e,
-                                    line,
-                                    column,
-                                });
-                            }
-                        }
-                        Expression::ArrayType { type_name: _, size } => {
-                            let initial_values = vec![Value::Undefined; *size as usize];
-                            let entry = SymbolEntry {
-                                name: name.clone(),
-                                entity_type: EntityType::Array { size: *size },
-                                data_type: data_type.clone(),
-                                value: Value::Array(initial_values),
-                                line,
-                                column,
-                            };
-
-                            if let Err(e) = self.symbol_table.insert(entry) {
-                                self.errors.push(SemanticError {
-                                    message: e,
-                                    line,
-                                    column,
-                                });
-                            }
-                        }
-                        _ => {} // Should not happen based on grammar
+            Declaration::VariableDecl { names, type_spec, initializer, .. } => {
+                let (data_type, dimensions) = self.resolve_type(type_spec);
+                let own_initializers = names.iter().filter_map(|(_, _, own)| own.as_ref());
+                for initializer in own_initializers.chain(initializer) {
+                    self.resolve_references(initializer);
+                }
+
+                // The shared initializer is checked before any name is declared, and
+                // is only used by the names without their own
+                let sharing = names.iter().find(|(_, _, own)| own.is_none());
+                let shared = match (initializer, sharing) {
+                    (Some(initializer), Some((name, ..))) => {
+                        self.check_variable_initializer(initializer, &data_type, &dimensions, name)
+                    }
+                    (Some(initializer), None) => {
+                        self.check_variable_initializer(initializer, &data_type, &dimensions, &names[0].0);
+                        self.warn(
+                            "Unused initializer, every name of the declaration has its own".to_string(),
+                            initializer.span(),
+                            Vec::new(),
+                        );
+                        None
                     }
+                    (None, _) => None,
+                };
+
+                for (name, name_span, own) in names {
+                    // An initializer of its own may use the names declared before it
+                    let contents = match own {
+                        Some(own) => self.check_variable_initializer(own, &data_type, &dimensions, name),
+                        None => shared.clone(),
+                    };
+                    let (line, column) = self.line_index.line_column(name_span.start);
+
+                    let (entity_type, value) = if dimensions.is_empty() {
+                        (EntityType::Variable, contents.clone().unwrap_or(Value::Undefined))
+                    } else {
+                        (
+                            EntityType::Array { dimensions: dimensions.clone(), constant: false },
+                            contents.clone().unwrap_or_else(|| Value::empty_array(&dimensions)),
+                        )
+                    };
+                    let entry = SymbolEntry {
+                        name: name.clone(),
+                        entity_type,
+                        data_type: data_type.clone(),
+                        value,
+                        line,
+                        column,
+                        span: *name_span,
+                        scope: self.symbol_table.current_scope(),
+                    };
+
+                    self.declare(entry);
                 }
             }
             Declaration::ConstDecl {
                 name,
-                type_name,
+                name_span,
+                type_spec,
                 value,
+                span,
             } => {
-                let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                let data_type = match type_name.as_str() {
-                    "Int" => DataType::Int,
-                    "Float" => DataType::Float,
-                    _ => {
-                        self.errors.push(SemanticError {
-                            message: format!("Unknown type: {}", type_name),
-                            line,
-                            column,
-                        });
-                        return;
-                    }
+                // A constant used further up the list has already been declared
+                if !self.resolved_constants.insert(*span) {
+                    return;
+                }
+                if self.pending_constants.get(name).map(Declaration::span) == Some(*span) {
+                    self.pending_constants.remove(name);
+                }
+
+                self.evaluating.push(name.clone());
+                self.process_constant(name, *name_span, type_spec, value);
+                self.evaluating.pop();
+            }
+            Declaration::Function {
+                name,
+                name_span,
+                params,
+                return_type,
+                declarations,
+                body,
+                ..
+            } => {
+                if self.current_function.is_some() {
+                    self.report(
+                        ErrorCode::NestedFunction,
+                        format!("Function '{}' must be declared at the top level", name),
+                        *name_span,
+                    );
+                    return;
+                }
+
+                let return_type = return_type.as_deref().map_or(DataType::Void, type_named);
+                let param_types: Vec<(String, DataType)> = params
+                    .iter()
+                    .map(|param| (param.name.clone(), type_named(&param.type_name)))
+                    .collect();
+
+                // The function is declared before its body is checked, so that it can recurse
+                let (line, column) = self.line_index.line_column(name_span.start);
+                let entry = SymbolEntry {
+                    name: name.clone(),
+                    entity_type: EntityType::Function {
+                        params: param_types.clone(),
+                        scope: self.symbol_table.scopes.len(), // Id the body's scope is about to get
+                    },
+                    data_type: return_type.clone(),
+                    value: Value::Undefined,
+                    line,
+                    column,
+                    span: *name_span,
+                    scope: self.symbol_table.current_scope(),
                 };
+                self.declare(entry);
 
-                // Evaluate constant value
-                if let Some(const_value) = self.evaluate_constant(value) {
-                    // Check type compatibility
-                    match (&data_type, &const_value) {
-                        (DataType::Int, Value::Int(_)) | (DataType::Float, Value::Float(_)) => {
-                            // Types match, insert into symbol table
-                            let entry = SymbolEntry {
-                                name: name.clone(),
-                                entity_type: EntityType::Constant,
-                                data_type,
-                                value: const_value,
-                                line,
-                                column,
-                            };
-
-                            if let Err(e) = self.symbol_table.insert(entry) {
-                                self.errors.push(SemanticError {
-                                    message: e,
-                                    line,
-                                    column,
-                                });
-                            }
+                self.symbol_table.enter_scope(name);
+                for (param, (_, data_type)) in params.iter().zip(param_types) {
+                    let (line, column) = self.line_index.line_column(param.span.start);
+                    let entry = SymbolEntry {
+                        name: param.name.clone(),
+                        entity_type: EntityType::Variable,
+                        data_type,
+                        value: Value::Undefined,
+                        line,
+                        column,
+                        span: param.span,
+                        scope: self.symbol_table.current_scope(),
+                    };
+                    self.declare(entry);
+                }
+
+                self.current_function = Some((name.clone(), return_type.clone()));
+                self.process_declarations(declarations);
+                for stmt in body {
+                    self.process_statement(stmt);
+                }
+                self.current_function = None;
+                self.symbol_table.exit_scope();
+
+                if return_type != DataType::Void && !always_returns(body) {
+                    self.report(
+                        ErrorCode::MissingReturn,
+                        format!("Function '{}' may finish without returning a value", name),
+                        *name_span,
+                    );
+                }
+            }
+            // Already reported by the parser
+            Declaration::Error { .. } => {}
+        }
+    }
+
+    // A constant is declared even if its value is wrong, so that its uses are still checked
+    fn process_constant(&mut self, name: &str, name_span: Span, type_spec: &Expression, value: &Expression) {
+        let (data_type, dimensions) = self.resolve_type(type_spec);
+        self.resolve_references(value);
+
+        // The cycle a constant is part of has already been reported
+        let cyclic = self.cyclic_constants.contains(name);
+        let (entity_type, const_value) = if !dimensions.is_empty() {
+            let contents = if cyclic {
+                None
+            } else {
+                self.check_initializer(value, &data_type, &dimensions)
+            };
+            (
+                EntityType::Array { dimensions: dimensions.clone(), constant: true },
+                contents.unwrap_or_else(|| Value::empty_array(&dimensions)),
+            )
+        } else if cyclic {
+            (EntityType::Constant, Value::Undefined)
+        } else {
+            let const_value = self.check_constant_value(name, &data_type, value);
+            (EntityType::Constant, const_value.unwrap_or(Value::Undefined))
+        };
+
+        let (line, column) = self.line_index.line_column(name_span.start);
+        let entry = SymbolEntry {
+            name: name.to_string(),
+            entity_type,
+            data_type,
+            value: const_value,
+            line,
+            column,
+            span: name_span,
+            scope: self.symbol_table.current_scope(),
+        };
+        self.declare(entry);
+    }
+
+    // Folds the value of a scalar constant and checks it against the declared type
+    fn check_constant_value(&mut self, name: &str, data_type: &DataType, value: &Expression) -> Option<Value> {
+        if self.check_expression(value) == Type::Error {
+            return None;
+        }
+        let const_value = match self.evaluate_constant(value) {
+            Some(const_value) => const_value,
+            None => {
+                self.report_constant_failure(value, format!("Could not evaluate constant value for '{}'", name));
+                return None;
+            }
+        };
+
+        // Check type compatibility, widening an Int value for a Float constant
+        let const_value = match (data_type, const_value) {
+            (DataType::Float, Value::Int(n)) => Value::Float(n as f32),
+            (_, const_value) => const_value,
+        };
+        match (data_type, &const_value) {
+            (DataType::Int, Value::Int(_))
+            | (DataType::Float, Value::Float(_))
+            | (DataType::Bool, Value::Bool(_))
+            | (DataType::String, Value::String(_)) => Some(const_value),
+            _ => {
+                self.report(
+                    ErrorCode::TypeMismatch,
+                    format!(
+                        "Type mismatch for constant '{}': expected {:?}, got {:?}",
+                        name, data_type, const_value
+                    ),
+                    value.span(),
+                );
+                None
+            }
+        }
+    }
+
+    // Data type and dimensions of a type specifier; array sizes have to be
+    // positive Int constants
+    fn resolve_type(&mut self, type_spec: &Expression) -> (DataType, Vec<i32>) {
+        let (type_name, sizes) = match type_spec.type_shape() {
+            Some(shape) => shape,
+            None => return (DataType::Int, Vec::new()), // Should not happen
+        };
+
+        let mut dimensions = Vec::new();
+        for size in sizes {
+            self.resolve_references(size);
+            let size_type = self.check_expression(size);
+            let dimension = match (size_type, self.evaluate_constant(size)) {
+                (_, Some(Value::Int(n))) if n > 0 => Some(n),
+                (Type::Error, _) => None,
+                (Type::Int, Some(Value::Int(n))) => {
+                    self.report(
+                        ErrorCode::InvalidConstant,
+                        format!("Array size must be positive, found {}", n),
+                        size.span(),
+                    );
+                    None
+                }
+                (Type::Int, _) => {
+                    self.report_constant_failure(size, "Array size must be a constant".to_string());
+                    None
+                }
+                (size_type, _) => {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("Array size must be Int, found {}", size_type),
+                        size.span(),
+                    );
+                    None
+                }
+            };
+            // A wrong size is replaced so that the rest of the declaration is still checked
+            dimensions.push(dimension.unwrap_or(1));
+        }
+
+        let elements = dimensions
+            .iter()
+            .try_fold(1usize, |total, &size| total.checked_mul(size as usize))
+            .filter(|&total| total <= MAX_ARRAY_ELEMENTS);
+        if elements.is_none() {
+            let sizes: Vec<String> = dimensions.iter().map(i32::to_string).collect();
+            self.report(
+                ErrorCode::ArrayTooLarge,
+                format!(
+                    "Array of {} elements is larger than the limit of {} elements",
+                    sizes.join(" x "),
+                    MAX_ARRAY_ELEMENTS
+                ),
+                type_spec.span(),
+            );
+            dimensions = vec![1; dimensions.len()];
+        }
+        (type_named(type_name), dimensions)
+    }
+
+    // Declares the constants of the current declaration list that `expr` refers to
+    // ahead of their own declaration, and reports the references that close a cycle
+    fn resolve_references(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Var(var) => {
+                let (name, span) = match var {
+                    Variable::Simple(name, span) => (name, *span),
+                    Variable::Array { name, indices, span } => {
+                        for index in indices {
+                            self.resolve_references(index);
                         }
-                        _ => {
-                            self.errors.push(SemanticError {
-                                message: format!(
-                                    "Type mismatch for constant '{}': expected {:?}, got {:?}",
-                                    name, data_type, const_value
-                                ),
-                                line,
-                                column,
-                            });
+                        (name, *span)
+                    }
+                };
+
+                let position = self.evaluating.iter().position(|evaluating| evaluating == name);
+                if let (Some(position), None) = (position, self.symbol_table.lookup_current(name)) {
+                    let mut cycle = self.evaluating[position..].to_vec();
+                    cycle.push(name.clone());
+                    self.report(
+                        ErrorCode::InvalidConstant,
+                        format!("Cyclic definition of constant '{}': {}", name, cycle.join(" -> ")),
+                        span,
+                    );
+                    self.cyclic_constants.extend(cycle);
+                } else if let Some(decl) = self.pending_constants.get(name).cloned() {
+                    self.process_declaration(&decl);
+                }
+            }
+            Expression::Literal(inner)
+            | Expression::Not(inner, _)
+            | Expression::Negate(inner, _)
+            | Expression::Plus(inner, _) => self.resolve_references(inner),
+            Expression::Binary { left, right, .. } => {
+                self.resolve_references(left);
+                self.resolve_references(right);
+            }
+            Expression::Call { args: elements, .. } | Expression::ArrayLiteral { elements, .. } => {
+                for element in elements {
+                    self.resolve_references(element);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Reports why an expression that has to be constant could not be folded,
+    // pointing at the innermost part that failed
+    fn report_constant_failure(&mut self, expr: &Expression, fallback: String) {
+        let (code, message, span) = match self.constant_failure(expr) {
+            Some(ConstantFailure::Error(code, message, span)) => (code, message, span),
+            Some(ConstantFailure::Reported) => return,
+            None => (ErrorCode::InvalidConstant, fallback, expr.span()),
+        };
+        let reported = self.errors.iter().any(|e| e.code == Some(code) && e.span == span);
+        if !reported {
+            self.report(code, message, span);
+        }
+    }
+
+    fn constant_failure(&self, expr: &Expression) -> Option<ConstantFailure> {
+        if self.evaluate_constant(expr).is_some() {
+            return None;
+        }
+
+        match expr {
+            Expression::Literal(inner) | Expression::Not(inner, _) | Expression::Plus(inner, _) => {
+                self.constant_failure(inner)
+            }
+            Expression::Var(var) => {
+                let (name, span) = match var {
+                    Variable::Simple(name, span) => (name, *span),
+                    Variable::Array { name, indices, span } => {
+                        if let Some(failure) = indices.iter().find_map(|index| self.constant_failure(index)) {
+                            return Some(failure);
                         }
+                        (name, *span)
                     }
-                } else {
-                    self.errors.push(SemanticError {
-                        message: format!("Could not evaluate constant value for '{}'", name),
-                        line,
-                        column,
-                    });
+                };
+                let entry = self.symbol_table.lookup(name)?;
+                match entry.entity_type {
+                    EntityType::Constant if entry.value == Value::Undefined => Some(ConstantFailure::Reported),
+                    EntityType::Constant | EntityType::Array { constant: true, .. } => None,
+                    _ => Some(ConstantFailure::Error(
+                        ErrorCode::InvalidConstant,
+                        format!("'{}' is not a constant and cannot be used in a constant expression", name),
+                        span,
+                    )),
                 }
             }
+            Expression::Binary { left, op, right, span } => {
+                if let Some(failure) = self.constant_failure(left).or_else(|| self.constant_failure(right)) {
+                    return Some(failure);
+                }
+                // Both operands fold, so the operator itself failed
+                match (self.evaluate_constant(left)?, self.evaluate_constant(right)?) {
+                    (_, Value::Int(0) | Value::Float(0.0)) if op.divides() => Some(
+                        ConstantFailure::Error(ErrorCode::DivisionByZero, "Division by zero".to_string(), right.span()),
+                    ),
+                    // Reported by `check_expression`
+                    (Value::Int(_), Value::Int(r)) if matches!(op, BinaryOp::Power) && r < 0 => {
+                        Some(ConstantFailure::Reported)
+                    }
+                    (Value::Int(l), Value::Int(r)) => Some(ConstantFailure::Error(
+                        ErrorCode::IntegerOverflow,
+                        format!("Integer overflow: {} {} {} does not fit in Int", l, operator_symbol(op), r),
+                        *span,
+                    )),
+                    _ => None,
+                }
+            }
+            Expression::Negate(inner, span) => self.constant_failure(inner).or_else(|| {
+                match self.evaluate_constant(inner)? {
+                    Value::Int(n) => Some(ConstantFailure::Error(
+                        ErrorCode::IntegerOverflow,
+                        format!("Integer overflow: -({}) does not fit in Int", n),
+                        *span,
+                    )),
+                    _ => None,
+                }
+            }),
+            Expression::Call { name, span, .. } => Some(ConstantFailure::Error(
+                ErrorCode::InvalidConstant,
+                format!("Function '{}' cannot be called in a constant expression", name),
+                *span,
+            )),
+            _ => None,
         }
     }
 
+    fn process_loop_body(&mut self, body: &[Statement]) {
+        self.loop_depth += 1;
+        for stmt in body {
+            self.process_statement(stmt);
+        }
+        self.loop_depth -= 1;
+    }
+
     fn process_statement(&mut self, stmt: &Statement) {
         match stmt {
-            Statement::Assignment { target, value } => {
+            Statement::Assignment { target, value, .. } => {
                 self.check_assignment(target, value);
             }
-            Statement::IfElse {
-                condition,
-                if_branch,
+            Statement::IfChain {
+                branches,
                 else_branch,
+                ..
             } => {
-                self.check_condition(condition);
+                for branch in branches {
+                    self.check_condition(&branch.condition);
 
-                for stmt in if_branch {
-                    self.process_statement(stmt);
+                    for stmt in &branch.body {
+                        self.process_statement(stmt);
+                    }
                 }
 
                 for stmt in else_branch {
                     self.process_statement(stmt);
                 }
             }
-            Statement::DoWhile { condition, body } => {
+            Statement::DoWhile { condition, body, .. } | Statement::While { condition, body, .. } => {
                 self.check_condition(condition);
-
-                for stmt in body {
-                    self.process_statement(stmt);
-                }
+                self.process_loop_body(body);
             }
             Statement::For {
                 var,
+                var_span,
                 start,
                 end,
                 step,
                 body,
+                ..
             } => {
-                // Check if variable exists
-                if self.symbol_table.lookup(var).is_none() {
-                    let (line, column) = self.source_map.get(var).unwrap_or(&(0, 0)).clone();
-                    self.errors.push(SemanticError {
-                        message: format!("Undeclared identifier: '{}'", var),
-                        line,
-                        column,
-                    });
-                } else {
-                    // Initialize loop variable with start value if possible
-                    if let Some(start_val) = self.evaluate_expression(start) {
-                        if let Err(e) = self.symbol_table.update_value(var, start_val) {
-                            let (line, column) =
-                                self.source_map.get(var).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: e,
-                                line,
-                                column,
-                            });
-                        }
+                // The loop variable must be a numeric variable that start, end and step fit into
+                self.check_loop_variable(var, *var_span);
+                let mut var_type = self.check_writable(var, *var_span);
+                if let Some(data_type @ (DataType::Bool | DataType::String)) = &var_type {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("The variable of a for loop must be Int or Float, '{}' is {:?}", var, data_type),
+                        *var_span,
+                    );
+                    var_type = None;
+                }
+
+                for bound in [start, end, step] {
+                    let bound_type = self.check_expression(bound);
+                    if let Some(data_type) = &var_type {
+                        self.check_assignable(data_type, &bound_type, bound.span(), var);
                     }
                 }
 
-                // Check expressions
-                self.check_expression(start);
-                self.check_expression(end);
-                self.check_expression(step);
+                // The loop counts up for a positive step and down for a negative one,
+                // a zero step would never reach the end
+                if let Some(Value::Int(0) | Value::Float(0.0)) = self.evaluate_constant(step) {
+                    self.report(
+                        ErrorCode::ZeroLoopStep,
+                        "The step of a for loop cannot be zero".to_string(),
+                        step.span(),
+                    );
+                }
 
-                // Process body
-                for stmt in body {
+                // Process body; the loop variable only changes through the step
+                self.loop_variables.push((var.clone(), *var_span));
+                self.process_loop_body(body);
+                self.loop_variables.pop();
+            }
+            Statement::Switch { subject, cases, default, .. } => {
+                match self.check_expression(subject) {
+                    Type::Int | Type::Error => {}
+                    found => self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("Expected an Int to switch on, found {}", found),
+                        subject.span(),
+                    ),
+                }
+
+                // Each label value may only be used once in a switch
+                let mut seen: HashMap<i32, Span> = HashMap::new();
+                for case in cases {
+                    for label in &case.labels {
+                        let value = match self.check_case_label(label) {
+                            Some(value) => value,
+                            None => continue,
+                        };
+                        match seen.get(&value) {
+                            Some(first) => {
+                                let labels = vec![Label::new(*first, "first used here")];
+                                self.report_with_labels(
+                                    ErrorCode::DuplicateCaseLabel,
+                                    format!("Duplicate case label {}", value),
+                                    label.span(),
+                                    labels,
+                                );
+                            }
+                            None => {
+                                seen.insert(value, label.span());
+                            }
+                        }
+                    }
+
+                    for stmt in &case.body {
+                        self.process_statement(stmt);
+                    }
+                }
+
+                for stmt in default.iter().flatten() {
                     self.process_statement(stmt);
                 }
             }
-            Statement::Input { var } => {
-                if self.symbol_table.lookup(var).is_none() {
-                    let (line, column) = self.source_map.get(var).unwrap_or(&(0, 0)).clone();
-                    self.errors.push(SemanticError {
-                        message: format!("Undeclared identifier: '{}'", var),
-                        line,
-                        column,
-                    });
-                } else {
-                    // For input statements, mark the variable as having a runtime value
-                    // We can't know what the value will be at compile time
-                    // But we should mark that it's been assigned
-                    if let Err(e) = self.symbol_table.update_value(var, Value::Undefined) {
-                        let (line, column) = self.source_map.get(var).unwrap_or(&(0, 0)).clone();
-                        self.errors.push(SemanticError {
-                            message: e,
-                            line,
-                            column,
-                        });
+            Statement::Input { var, var_span, .. } => {
+                self.check_loop_variable(var, *var_span);
+                self.check_writable(var, *var_span);
+            }
+            Statement::Output { expressions, .. } => {
+                for expr in expressions {
+                    match self.check_expression(expr) {
+                        Type::Array { .. } => self.report(
+                            ErrorCode::TypeMismatch,
+                            "Cannot output a whole array, index one of its elements".to_string(),
+                            expr.span(),
+                        ),
+                        Type::Void => self.report(
+                            ErrorCode::TypeMismatch,
+                            "Cannot output the result of a procedure, it has no value".to_string(),
+                            expr.span(),
+                        ),
+                        _ => {}
                     }
                 }
             }
-            Statement::Output { expressions } => {
-                for expr in expressions {
-                    self.check_expression(expr);
+            Statement::Call {
+                name,
+                name_span,
+                args,
+                ..
+            } => {
+                self.check_call(name, *name_span, args);
+            }
+            Statement::Return { value, span } => self.check_return(value.as_ref(), *span),
+            Statement::Break { span } | Statement::Continue { span } => {
+                if self.loop_depth == 0 {
+                    let keyword = if matches!(stmt, Statement::Break { .. }) { "break" } else { "continue" };
+                    self.report(
+                        ErrorCode::LoopControlOutsideLoop,
+                        format!("'{}' outside of a loop", keyword),
+                        *span,
+                    );
+                }
+            }
+            // Already reported by the parser
+            Statement::Error { .. } => {}
+        }
+    }
+
+    // Folds a case label, which has to be an Int constant
+    fn check_case_label(&mut self, label: &Expression) -> Option<i32> {
+        match self.check_expression(label) {
+            Type::Int => {}
+            Type::Error => return None,
+            found => {
+                self.report(
+                    ErrorCode::TypeMismatch,
+                    format!("Case label must be Int, found {}", found),
+                    label.span(),
+                );
+                return None;
+            }
+        }
+        match self.evaluate_constant(label) {
+            Some(Value::Int(n)) => Some(n),
+            _ => {
+                self.report_constant_failure(label, "Case label must be a constant".to_string());
+                None
+            }
+        }
+    }
+
+    // Reports a write to the variable of an enclosing for loop
+    fn check_loop_variable(&mut self, name: &str, span: Span) {
+        if let Some((_, loop_span)) = self.loop_variables.iter().rev().find(|(var, _)| var == name) {
+            let labels = vec![Label::new(*loop_span, "loop variable of this for loop")];
+            self.report_with_labels(
+                ErrorCode::LoopVariableAssignment,
+                format!("Cannot assign to '{}' inside the for loop it controls", name),
+                span,
+                labels,
+            );
+        }
+    }
+
+    // Checks that a name refers to a scalar variable that can be written to as a
+    // whole (by input or as a loop variable) and returns its type
+    fn check_writable(&mut self, name: &str, span: Span) -> Option<DataType> {
+        let entry = match self.symbol_table.lookup(name) {
+            Some(entry) => entry.clone(),
+            None => {
+                self.report_undeclared(name, span);
+                return None;
+            }
+        };
+
+        match entry.entity_type {
+            EntityType::Variable => Some(entry.data_type),
+            EntityType::Constant => {
+                let labels = vec![Label::new(entry.span, "constant declared here")];
+                self.report_with_labels(
+                    ErrorCode::ConstantAssignment,
+                    format!("Cannot modify constant: '{}'", name),
+                    span,
+                    labels,
+                );
+                None
+            }
+            EntityType::Array { .. } => {
+                let labels = self.declared_here(name, format!("'{}' declared here", name));
+                self.report_with_labels(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot assign to the whole array '{}'", name),
+                    span,
+                    labels,
+                );
+                None
+            }
+            EntityType::Function { .. } => {
+                let labels = self.declared_here(name, format!("'{}' declared here", name));
+                self.report_with_labels(
+                    ErrorCode::TypeMismatch,
+                    format!("Cannot assign to function '{}'", name),
+                    span,
+                    labels,
+                );
+                None
+            }
+        }
+    }
+
+    // Checks the arguments of a call against the parameters of the function and
+    // returns the type of its result
+    fn check_call(&mut self, name: &str, name_span: Span, args: &[Expression]) -> Type {
+        let entry = match self.symbol_table.lookup(name) {
+            Some(entry) => entry.clone(),
+            // Builtins can be hidden by a declaration with the same name
+            None if name == BUILTIN_LEN => return self.check_len(name_span, args),
+            None => {
+                self.report_undeclared(name, name_span);
+                for arg in args {
+                    self.check_expression(arg);
+                }
+                return Type::Error;
+            }
+        };
+
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.check_expression(arg)).collect();
+        let params = match entry.entity_type {
+            EntityType::Function { params, .. } => params,
+            _ => {
+                let labels = vec![Label::new(entry.span, format!("'{}' declared here", name))];
+                self.report_with_labels(
+                    ErrorCode::NotAFunction,
+                    format!("'{}' is not a function", name),
+                    name_span,
+                    labels,
+                );
+                return Type::Error;
+            }
+        };
+
+        if args.len() != params.len() {
+            let labels = vec![Label::new(entry.span, format!("'{}' declared here", name))];
+            self.report_with_labels(
+                ErrorCode::ArgumentCountMismatch,
+                format!(
+                    "Function '{}' takes {} argument(s) but {} were supplied",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+                name_span,
+                labels,
+            );
+        } else {
+            for ((arg, arg_type), (param, param_type)) in args.iter().zip(&arg_types).zip(&params) {
+                if !arg_type.is_assignable_to(param_type) {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "Mismatched types: parameter '{}' of '{}' is {}, found {}",
+                            param,
+                            name,
+                            Type::scalar(param_type),
+                            arg_type
+                        ),
+                        arg.span(),
+                    );
                 }
             }
         }
+
+        Type::scalar(&entry.data_type)
+    }
+
+    // `len(s)` is the number of characters of the string `s`
+    fn check_len(&mut self, name_span: Span, args: &[Expression]) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.check_expression(arg)).collect();
+        match arg_types.as_slice() {
+            [Type::String | Type::Error] => {}
+            [arg_type] => self.report(
+                ErrorCode::TypeMismatch,
+                format!("Mismatched types: 'len' expects a String, found {}", arg_type),
+                args[0].span(),
+            ),
+            _ => self.report(
+                ErrorCode::ArgumentCountMismatch,
+                format!("Function 'len' takes 1 argument(s) but {} were supplied", args.len()),
+                name_span,
+            ),
+        }
+        Type::Int
+    }
+
+    fn check_return(&mut self, value: Option<&Expression>, span: Span) {
+        let value_type = value.map(|value| self.check_expression(value));
+        let (name, return_type) = match &self.current_function {
+            Some(function) => function.clone(),
+            None => {
+                self.report(
+                    ErrorCode::ReturnOutsideFunction,
+                    "'return' outside of a function".to_string(),
+                    span,
+                );
+                return;
+            }
+        };
+
+        match (value, value_type, return_type) {
+            (None, _, DataType::Void) => {}
+            (Some(value), _, DataType::Void) => self.report(
+                ErrorCode::TypeMismatch,
+                format!("Procedure '{}' cannot return a value", name),
+                value.span(),
+            ),
+            (None, _, return_type) => self.report(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Function '{}' must return a value of type {}",
+                    name,
+                    Type::scalar(&return_type)
+                ),
+                span,
+            ),
+            (Some(value), Some(value_type), return_type) => {
+                if !value_type.is_assignable_to(&return_type) {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!(
+                            "Mismatched types: function '{}' returns {}, found {}",
+                            name,
+                            Type::scalar(&return_type),
+                            value_type
+                        ),
+                        value.span(),
+                    );
+                }
+            }
+            (Some(_), None, _) => unreachable!(),
+        }
+    }
+
+    // Reports a value whose type does not fit a target of type `target`
+    fn check_assignable(&mut self, target: &DataType, value: &Type, span: Span, name: &str) {
+        if !value.is_assignable_to(target) {
+            self.report(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Mismatched types: cannot assign {} to '{}' of type {}",
+                    value,
+                    name,
+                    Type::scalar(target)
+                ),
+                span,
+            );
+        }
     }
 
     fn check_assignment(&mut self, target: &Variable, value: &Expression) {
         match target {
-            Variable::Simple(name) => {
-                // Check if variable exists
-                let entry = match self.symbol_table.lookup(name) {
-                    Some(entry) => entry,
+            Variable::Simple(name, span) => {
+                self.check_loop_variable(name, *span);
+                let data_type = match self.check_writable(name, *span) {
+                    Some(data_type) => data_type,
                     None => {
-                        let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                        self.errors.push(SemanticError {
-                            message: format!("Undeclared identifier: '{}'", name),
-                            line,
-                            column,
-                        });
+                        self.check_expression(value);
                         return;
                     }
                 };
 
+                let value_type = self.check_expression(value);
+                self.check_assignable(&data_type, &value_type, value.span(), name);
+            }
+            Variable::Array { name, span, .. } => {
+                let element_type = self.check_variable(target);
+                let value_type = self.check_expression(value);
 
-                // Check if assigning to constant
-                if let EntityType::Constant = entry.entity_type {
-                    let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                    self.errors.push(SemanticError {
-                        message: format!("Cannot modify constant: '{}'", name),
-                        line,
-                        column,
-                    });
-                    return;
+                // Neither the elements of a constant array nor the characters of a
+                // constant string can be modified
+                if let Some(entry) = self.symbol_table.lookup(name) {
+                    if matches!(entry.entity_type, EntityType::Constant | EntityType::Array { constant: true, .. }) {
+                        let labels = vec![Label::new(entry.span, "constant declared here")];
+                        self.report_with_labels(
+                            ErrorCode::ConstantAssignment,
+                            format!("Cannot modify constant: '{}'", name),
+                            *span,
+                            labels,
+                        );
+                        return;
+                    }
                 }
 
-                 // Check for string literals in assignment
-                 if let Expression::String(s) = value {
-                    let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                    self.errors.push(SemanticError {
-                        message: format!("Cannot assign string '{}' to variable '{}' of type {:?}", s, name, entry.data_type),
-                        line,
-                        column,
-                    });
+                // Strings are values, a character cannot be replaced in place
+                if let Some(entry) = self.symbol_table.lookup(name) {
+                    if entry.entity_type == EntityType::Variable && entry.data_type == DataType::String {
+                        let labels = self.declared_here(name, format!("'{}' declared here", name));
+                        self.report_with_labels(
+                            ErrorCode::TypeMismatch,
+                            format!("Cannot assign to a character of the string '{}', assign the whole string instead", name),
+                            *span,
+                            labels,
+                        );
+                        return;
+                    }
+                }
+
+                if let Type::Error = element_type {
                     return;
                 }
+                if let Some(entry) = self.symbol_table.lookup(name) {
+                    let data_type = entry.data_type.clone();
+                    self.check_assignable(&data_type, &value_type, value.span(), name);
+                }
+            }
+        }
+    }
 
-                // Check expression
-                self.check_expression(value);
-
-                // Special case for literal values - handle them directly
-                match value {
-                    Expression::Integer(n) => {
-                        let val = Value::Int(*n);
-                        if let Err(e) = self.symbol_table.update_value(name, val) {
-                            let (line, column) =
-                                self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: e,
-                                line,
-                                column,
-                            });
-                        }
-                        return;
-                    }
-                    Expression::Float(n) => {
-                        let val = Value::Float(*n);
-                        if let Err(e) = self.symbol_table.update_value(name, val) {
-                            let (line, column) =
-                                self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: e,
-                                line,
-                                column,
-                            });
-                        }
-                        return;
-                    }
-                    Expression::Literal(expr) => {
-                        // Handle unwrapping the literal value
-                        match &**expr {
-                            Expression::Integer(n) => {
-                                let val = Value::Int(*n);
-                                if let Err(e) = self.symbol_table.update_value(name, val) {
-                                    let (line, column) =
-                                        self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                                    self.errors.push(SemanticError {
-                                        message: e,
-                                        line,
-                                        column,
-                                    });
-                                }
-                                return;
-                            }
-                            Expression::Float(n) => {
-                                let val = Value::Float(*n);
-                                if let Err(e) = self.symbol_table.update_value(name, val) {
-                                    let (line, column) =
-                                        self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                                    self.errors.push(SemanticError {
-                                        message: e,
-                                        line,
-                                        column,
-                                    });
-                                }
-                                return;
-                            }
-                            _ => {} // Fall through to the general case
-                        }
-                    }
-                    _ => {} // Fall through to the general case
-                }
-                
-
-                // Try to evaluate the expression and update the symbol table
-                if let Some(evaluated_value) = self.evaluate_expression(value) {
-                    // Here we could add type checking between entry.data_type and evaluated_value
-                    // For now, just update the value
-                    if let Err(e) = self.symbol_table.update_value(name, evaluated_value) {
-                        let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                        self.errors.push(SemanticError {
-                            message: e,
-                            line,
-                            column,
-                        });
-                    }
-                } else {
-                    // If we can't evaluate at compile time, mark as having a runtime value
-                    if let Err(e) = self.symbol_table.update_value(name, Value::Undefined) {
-                        let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                        self.errors.push(SemanticError {
-                            message: e,
-                            line,
-                            column,
-                        });
+    // Infers the type of a variable reference; a plain name of an array has the
+    // array type, an indexed one has the element type
+    fn check_variable(&mut self, var: &Variable) -> Type {
+        match var {
+            Variable::Simple(name, span) => match self.symbol_table.lookup(name) {
+                Some(entry) => match entry.entity_type {
+                    EntityType::Array { ref dimensions, .. } => Type::Array {
+                        element: entry.data_type.clone(),
+                        dimensions: dimensions.clone(),
+                    },
+                    EntityType::Function { .. } => {
+                        let labels = vec![Label::new(entry.span, format!("'{}' declared here", name))];
+                        self.report_with_labels(
+                            ErrorCode::TypeMismatch,
+                            format!("Function '{}' used as a value, call it with '{}(...)'", name, name),
+                            *span,
+                            labels,
+                        );
+                        Type::Error
                     }
+                    _ => Type::scalar(&entry.data_type),
+                },
+                None => {
+                    self.report_undeclared(name, *span);
+                    Type::Error
                 }
-            }
-            Variable::Array { name, index } => {
-                // Check if array exists
+            },
+            Variable::Array { name, indices, span } => {
                 let entry = match self.symbol_table.lookup(name) {
-                    Some(entry) => entry,
+                    Some(entry) => entry.clone(),
                     None => {
-                        let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                        self.errors.push(SemanticError {
-                            message: format!("Undeclared identifier: '{}'", name),
-                            line,
-                            column,
-                        });
-                        return;
+                        self.report_undeclared(name, *span);
+                        for index in indices {
+                            self.check_expression(index);
+                        }
+                        return Type::Error;
                     }
                 };
-            
-                // Check if it's an array
-                if let EntityType::Array { size } = entry.entity_type {
-                    // Evaluate the index expression
-                    if let Some(Value::Int(idx)) = self.evaluate_constant(index) {
-                        if idx < 0 || idx >= size {
-                            let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: format!("Array index out of bounds: '{}[{}]', size is {}", name, idx, size),
-                                line,
-                                column,
-                            });
-                        } else {
-                            // Evaluate the value expression
-                            if let Some(value) = self.evaluate_expression(value) {
-                                // Update the array element at idx
-                                if let Err(e) = self.symbol_table.update_array_element(name, idx as usize, value) {
-                                    let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                                    self.errors.push(SemanticError {
-                                        message: e,
-                                        line,
-                                        column,
-                                    });
-                                }
-                            } else {
-                                // Mark element as Undefined if value can't be determined
-                                self.symbol_table.update_array_element(name, idx as usize, Value::Undefined).ok();
-                            }
+
+                // Indexing a string yields a string holding one character; only the
+                // length of a constant string is known here
+                let is_string = entry.data_type == DataType::String;
+                let sizes: Vec<Option<i32>> = match (&entry.entity_type, &entry.value) {
+                    (EntityType::Array { dimensions, .. }, _) => dimensions.iter().map(|size| Some(*size)).collect(),
+                    (EntityType::Constant, Value::String(s)) => vec![Some(s.chars().count() as i32)],
+                    (EntityType::Variable | EntityType::Constant, _) if is_string => vec![None],
+                    _ => {
+                        let labels = self.declared_here(name, format!("'{}' declared here", name));
+                        self.report_with_labels(
+                            ErrorCode::NotAnArray,
+                            format!("'{}' is not an array", name),
+                            *span,
+                            labels,
+                        );
+                        for index in indices {
+                            self.check_expression(index);
                         }
-                    } else {
-                        // Index isn't a constant; check expressions but can't track value
-                        self.check_expression(index);
+                        return Type::Error;
                     }
+                };
+
+                // Every dimension has to be indexed, elements are never used as whole rows
+                if indices.len() != sizes.len() {
+                    let labels = self.declared_here(name, format!("'{}' declared here", name));
+                    self.report_with_labels(
+                        if indices.len() > sizes.len() { ErrorCode::NotAnArray } else { ErrorCode::TypeMismatch },
+                        format!(
+                            "'{}' has {} dimension(s) but {} index(es) were given",
+                            name,
+                            sizes.len(),
+                            indices.len()
+                        ),
+                        *span,
+                        labels,
+                    );
+                }
+
+                for (dimension, index) in indices.iter().enumerate() {
+                    let size = sizes.get(dimension).copied().flatten();
+                    self.check_index(name, &entry, index, dimension, size, sizes.len());
+                }
+
+                if indices.len() == sizes.len() {
+                    Type::scalar(&entry.data_type)
                 } else {
-                    let (line, column) = self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                    self.errors.push(SemanticError {
-                        message: format!("'{}' is not an array", name),
-                        line,
-                        column,
-                    });
+                    Type::Error
                 }
-            
-                // Check the value expression
-                self.check_expression(value);
             }
         }
     }
 
-    fn check_expression(&mut self, expr: &Expression) {
-        match expr {
-            Expression::Var(var) => {
-                match var {
-                    Variable::Simple(name) => {
-                        if self.symbol_table.lookup(name).is_none() {
-                            let (line, column) =
-                                self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: format!("Undeclared identifier: '{}'", name),
-                                line,
-                                column,
-                            });
-                        }
-                    }
-                    Variable::Array { name, index } => {
-                        if let Some(entry) = self.symbol_table.lookup(name) {
-                            if let EntityType::Array { size } = entry.entity_type {
-                                // Check index bounds if possible
-                                if let Some(idx_val) = self.evaluate_constant(index) {
-                                    if let Value::Int(idx) = idx_val {
-                                        if idx < 0 || idx >= size {
-                                            let (line, column) = self
-                                                .source_map
-                                                .get(name)
-                                                .unwrap_or(&(0, 0))
-                                                .clone();
-                                            self.errors.push(SemanticError {
-                                                message: format!("Array index out of bounds: '{}[{}]', size is {}", 
-                                                    name, idx, size),
-                                                line,
-                                                column,
-                                            });
-                                        }
-                                    }
-                                }
-                                // Check the index expression
-                                self.check_expression(index);
-                            } else {
-                                let (line, column) =
-                                    self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                                self.errors.push(SemanticError {
-                                    message: format!("'{}' is not an array", name),
-                                    line,
-                                    column,
-                                });
-                            }
+    // Checks that an index is an Int and, when both are known, that it is below `size`
+    fn check_index(
+        &mut self,
+        name: &str,
+        entry: &SymbolEntry,
+        index: &Expression,
+        dimension: usize,
+        size: Option<i32>,
+        dimension_count: usize,
+    ) {
+        let is_string = entry.data_type == DataType::String && dimension_count == 1
+            && !matches!(entry.entity_type, EntityType::Array { .. });
+        let kind = if is_string { "String" } else { "Array" };
+
+        match self.check_expression(index) {
+            Type::Int => {
+                if let (Some(Value::Int(idx)), Some(size)) = (self.evaluate_constant(index), size) {
+                    if idx < 0 || idx >= size {
+                        let measure = if is_string { "length" } else { "size" };
+                        let labels = vec![Label::new(
+                            entry.span,
+                            format!("'{}' declared here with {} {}", name, measure, size),
+                        )];
+                        let message = if dimension_count == 1 {
+                            format!("{} index out of bounds: '{}[{}]', {} is {}", kind, name, idx, measure, size)
                         } else {
-                            let (line, column) =
-                                self.source_map.get(name).unwrap_or(&(0, 0)).clone();
-                            self.errors.push(SemanticError {
-                                message: format!("Undeclared identifier: '{}'", name),
-                                line,
-                                column,
-                            });
-                        }
+                            format!(
+                                "Array index out of bounds: {} in dimension {} of '{}', size is {}",
+                                idx,
+                                dimension + 1,
+                                name,
+                                size
+                            )
+                        };
+                        self.report_with_labels(ErrorCode::IndexOutOfBounds, message, index.span(), labels);
                     }
                 }
             }
-            Expression::Binary { left, op, right } => {
-                self.check_expression(left);
-                self.check_expression(right);
-
-                // Check for division by zero using evaluate_expression to track variable values
-                if let BinaryOp::Divide = op {
-                    if let Some(right_val) = self.evaluate_expression(right) {
-                        match right_val {
-                            Value::Int(0) | Value::Float(0.0) => {
-                                // Get the source position from the right expression if possible
-                                let (line, column) = self.get_expr_source_pos(right);
-                                self.errors.push(SemanticError {
-                                    message: "Division by zero".to_string(),
-                                    line,
-                                    column,
-                                });
-                            }
-                            _ => {}
-                        }
+            Type::Error => {}
+            index_type => {
+                self.report(
+                    ErrorCode::TypeMismatch,
+                    format!("{} index must be Int, found {}", kind, index_type),
+                    index.span(),
+                );
+            }
+        }
+    }
+
+    // Infers the type of an expression, reports the operands that do not fit
+    // their operator and records the result in `expression_types`
+    fn check_expression(&mut self, expr: &Expression) -> Type {
+        let expr_type = match expr {
+            Expression::Var(var) => self.check_variable(var),
+            Expression::Integer(..) => Type::Int,
+            Expression::Float(..) => Type::Float,
+            Expression::Bool(..) => Type::Bool,
+            Expression::String(..) => Type::String,
+            Expression::Literal(inner) => self.check_expression(inner),
+            Expression::Binary {
+                left,
+                op,
+                right,
+                span,
+            } => {
+                let left_type = self.check_expression(left);
+                let right_type = self.check_expression(right);
+
+                // Divisors that depend on variables are checked by constant propagation
+                if op.divides() {
+                    if let Some(Value::Int(0) | Value::Float(0.0)) = self.evaluate_constant(right) {
+                        self.report(ErrorCode::DivisionByZero, "Division by zero".to_string(), right.span());
                     }
                 }
 
-                // Type checking would be more extensive here
-            }
-            Expression::Not(expr) => {
-                self.check_expression(expr);
+                // An Int power is only an Int for exponents that are not negative
+                if let (BinaryOp::Power, Type::Int, Some(Value::Int(exponent))) =
+                    (op, &left_type, self.evaluate_constant(right))
+                {
+                    if exponent < 0 {
+                        self.report(
+                            ErrorCode::TypeMismatch,
+                            format!("Cannot raise an Int to the negative power {}, use a Float base", exponent),
+                            right.span(),
+                        );
+                    }
+                }
+
+                match binary_result_type(op, &left_type, &right_type) {
+                    Some(result) => result,
+                    None => {
+                        let labels = [(left, &left_type), (right, &right_type)]
+                            .into_iter()
+                            .map(|(operand, operand_type)| {
+                                Label::new(operand.span(), format!("this is {}", operand_type))
+                            })
+                            .collect();
+                        self.report_with_labels(
+                            ErrorCode::TypeMismatch,
+                            format!(
+                                "Cannot apply '{}' to {} and {}",
+                                operator_symbol(op),
+                                left_type,
+                                right_type
+                            ),
+                            *span,
+                            labels,
+                        );
+                        Type::Error
+                    }
+                }
             }
-            _ => {
-                // Other expression types are literals or types, no need to check
+            Expression::Not(inner, _) => match self.check_expression(inner) {
+                Type::Bool => Type::Bool,
+                Type::Error => Type::Error,
+                inner_type => {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("Cannot apply '!' to {}, expected a condition", inner_type),
+                        inner.span(),
+                    );
+                    Type::Error
+                }
+            },
+            Expression::Negate(inner, _) | Expression::Plus(inner, _) => match self.check_expression(inner) {
+                inner_type @ (Type::Int | Type::Float | Type::Error) => inner_type,
+                inner_type => {
+                    let verb = if matches!(expr, Expression::Negate(..)) { "negate" } else { "apply unary '+' to" };
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("Cannot {} {}", verb, inner_type),
+                        inner.span(),
+                    );
+                    Type::Error
+                }
+            },
+            Expression::Call {
+                name,
+                name_span,
+                args,
+                ..
+            } => self.check_call(name, *name_span, args),
+            // Lists are checked against the declared array type by `check_initializer`
+            Expression::ArrayLiteral { span, .. } => {
+                self.report(
+                    ErrorCode::TypeMismatch,
+                    "An initializer list can only initialize an array".to_string(),
+                    *span,
+                );
+                Type::Error
             }
+            // Type names only appear in declarations
+            Expression::Type(..) | Expression::ArrayType { .. } => Type::Error,
+        };
+
+        self.expression_types.insert(expr.span(), expr_type.clone());
+        expr_type
+    }
+
+    // Checks the initializer of a variable and returns its contents when they are
+    // constant; a scalar initializer that is not constant is only evaluated at runtime
+    fn check_variable_initializer(
+        &mut self,
+        initializer: &Expression,
+        data_type: &DataType,
+        dimensions: &[i32],
+        name: &str,
+    ) -> Option<Value> {
+        if !dimensions.is_empty() {
+            return self.check_initializer(initializer, data_type, dimensions);
+        }
+        let value_type = self.check_expression(initializer);
+        self.check_assignable(data_type, &value_type, initializer.span(), name);
+        match (data_type, self.evaluate_constant(initializer)) {
+            _ if !value_type.is_assignable_to(data_type) => None,
+            (DataType::Float, Some(Value::Int(n))) => Some(Value::Float(n as f32)),
+            (_, value) => value,
         }
     }
 
+    // Checks an initializer list against the element type and dimensions of the
+    // array it initializes, and folds it to the initial contents of the array
+    fn check_initializer(
+        &mut self,
+        initializer: &Expression,
+        element: &DataType,
+        dimensions: &[i32],
+    ) -> Option<Value> {
+        let (size, inner) = match dimensions.split_first() {
+            Some(split) => split,
+            None => return self.check_initializer_element(initializer, element),
+        };
+        let array_type = Type::Array {
+            element: element.clone(),
+            dimensions: dimensions.to_vec(),
+        };
 
-    /// Helper to get the source position of an expression (simplified)
-    fn get_expr_source_pos(&self, expr: &Expression) -> (usize, usize) {
-        match expr {
-            Expression::Var(var) => {
-                match var {
-                    Variable::Simple(name) => *self.source_map.get(name).unwrap_or(&(0, 0)),
-                    Variable::Array { name, .. } => *self.source_map.get(name).unwrap_or(&(0, 0)),
-                }
+        let elements = match initializer {
+            Expression::ArrayLiteral { elements, .. } => elements,
+            _ => {
+                self.check_expression(initializer);
+                self.report(
+                    ErrorCode::TypeMismatch,
+                    format!("Expected a list of {} elements for {}, found a single value", size, array_type),
+                    initializer.span(),
+                );
+                return None;
             }
-            // For literals, use default (0,0) as their positions aren't tracked
-            _ => (0, 0),
+        };
+
+        let mut complete = elements.len() == *size as usize;
+        if !complete {
+            self.report(
+                ErrorCode::InitializerCountMismatch,
+                format!(
+                    "Initializer has {} element(s) but {} has {}",
+                    elements.len(),
+                    array_type,
+                    size
+                ),
+                initializer.span(),
+            );
         }
-    }
 
-    fn check_condition(&mut self, condition: &Condition) {
-        match condition {
-            Condition::Expr(expr) => {
-                self.check_expression(expr);
+        let mut values = Vec::new();
+        for element_expr in elements {
+            match self.check_initializer(element_expr, element, inner) {
+                Some(value) => values.push(value),
+                None => complete = false,
             }
         }
+        complete.then_some(Value::Array(values))
     }
 
-    fn get_data_type(&self, type_expr: &Expression) -> DataType {
-        match type_expr {
-            Expression::Type(type_name) => {
-                match type_name.as_str() {
-                    "Int" => DataType::Int,
-                    "Float" => DataType::Float,
-                    _ => DataType::Int, // Default, but should not happen
-                }
-            }
-            Expression::ArrayType { type_name, size: _ } => {
-                match type_name.as_str() {
-                    "Int" => DataType::Int,
-                    "Float" => DataType::Float,
-                    _ => DataType::Int, // Default, but should not happen
-                }
+    // Elements have to be constants of the element type; Int widens to Float
+    fn check_initializer_element(&mut self, expr: &Expression, element: &DataType) -> Option<Value> {
+        if let Expression::ArrayLiteral { span, .. } = expr {
+            self.report(
+                ErrorCode::TypeMismatch,
+                format!("Expected a single {} value, found a list", Type::scalar(element)),
+                *span,
+            );
+            return None;
+        }
+
+        let value_type = self.check_expression(expr);
+        if value_type == Type::Error {
+            return None;
+        }
+        if !value_type.is_assignable_to(element) {
+            self.report(
+                ErrorCode::TypeMismatch,
+                format!(
+                    "Mismatched types: array elements are {}, found {}",
+                    Type::scalar(element),
+                    value_type
+                ),
+                expr.span(),
+            );
+            return None;
+        }
+
+        match (element, self.evaluate_constant(expr)) {
+            (DataType::Float, Some(Value::Int(n))) => Some(Value::Float(n as f32)),
+            (_, Some(value)) => Some(value),
+            (_, None) => {
+                self.report(
+                    ErrorCode::InvalidConstant,
+                    "Array initializer elements must be constant".to_string(),
+                    expr.span(),
+                );
+                None
             }
-            _ => DataType::Int, // Default, but should not happen
         }
     }
 
-    // This function is similar to evaluate_constant but handles more cases
-    // and is used to track runtime values in the symbol table
-    fn evaluate_expression(&self, expr: &Expression) -> Option<Value> {
-        match expr {
-            Expression::Integer(n) => Some(Value::Int(*n)),
-            Expression::Float(n) => Some(Value::Float(*n)),
-            Expression::String(_) => None, // We're not tracking string values in this example
-            Expression::Literal(inner_expr) => {
-                // Unwrap the literal and evaluate the inner expression
-                self.evaluate_expression(inner_expr)
-            }
-            Expression::Var(var) => {
-                match var {
-                    Variable::Simple(name) => {
-                        if let Some(entry) = self.symbol_table.lookup(name) {
-                            match &entry.value {
-                                Value::Undefined => None, // Value not determined at compile time
-                                _ => Some(entry.value.clone()),
-                            }
-                        } else {
-                            None
-                        }
-                    }
-                    Variable::Array { name: _, index: _ } => {
-                        // For array access, we'd need to track individual elements
-                        // For simplicity, we'll just return None for array elements
-                        None
-                    }
-                }
-            }
-            Expression::Binary { left, op, right } => {
-                if let (Some(left_val), Some(right_val)) = (
-                    self.evaluate_expression(left),
-                    self.evaluate_expression(right),
-                ) {
-                    match (left_val, right_val) {
-                        (Value::Int(left_int), Value::Int(right_int)) => {
-                            match op {
-                                BinaryOp::Add => Some(Value::Int(left_int + right_int)),
-                                BinaryOp::Subtract => Some(Value::Int(left_int - right_int)),
-                                BinaryOp::Multiply => Some(Value::Int(left_int * right_int)),
-                                BinaryOp::Divide => {
-                                    if right_int == 0 {
-                                        None // Division by zero error
-                                    } else {
-                                        Some(Value::Int(left_int / right_int))
-                                    }
-                                }
-                                _ => None, // Handle other operations if needed
-                            }
-                        }
-                        (Value::Float(left_float), Value::Float(right_float)) => {
-                            match op {
-                                BinaryOp::Add => Some(Value::Float(left_float + right_float)),
-                                BinaryOp::Subtract => Some(Value::Float(left_float - right_float)),
-                                BinaryOp::Multiply => Some(Value::Float(left_float * right_float)),
-                                BinaryOp::Divide => {
-                                    if right_float == 0.0 {
-                                        None // Division by zero error
-                                    } else {
-                                        Some(Value::Float(left_float / right_float))
-                                    }
-                                }
-                                _ => None, // Handle other operations if needed
-                            }
-                        }
-                        // Handle mixed types (Int and Float)
-                        (Value::Int(left_int), Value::Float(right_float)) => {
-                            // Convert int to float and perform float operation
-                            let left_float = left_int as f32;
-                            match op {
-                                BinaryOp::Add => Some(Value::Float(left_float + right_float)),
-                                BinaryOp::Subtract => Some(Value::Float(left_float - right_float)),
-                                BinaryOp::Multiply => Some(Value::Float(left_float * right_float)),
-                                BinaryOp::Divide => {
-                                    if right_float == 0.0 {
-                                        None // Division by zero
-                                    } else {
-                                        Some(Value::Float(left_float / right_float))
-                                    }
-                                }
-                                // Other operations would use similar logic
-                                _ => None,
-                            }
-                        }
-                        (Value::Float(left_float), Value::Int(right_int)) => {
-                            // Convert int to float and perform float operation
-                            let right_float = right_int as f32;
-                            match op {
-                                BinaryOp::Add => Some(Value::Float(left_float + right_float)),
-                                BinaryOp::Subtract => Some(Value::Float(left_float - right_float)),
-                                BinaryOp::Multiply => Some(Value::Float(left_float * right_float)),
-                                BinaryOp::Divide => {
-                                    if right_int == 0 {
-                                        None // Division by zero
-                                    } else {
-                                        Some(Value::Float(left_float / right_float))
-                                    }
-                                }
-                                // Other operations would use similar logic
-                                _ => None,
-                            }
-                        }
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
-            }
-            Expression::Not(expr) => {
-                // Logic for the Not operator remains the same
-                if let Some(val) = self.evaluate_expression(expr) {
-                    match val {
-                        Value::Int(i) => Some(Value::Int(if i == 0 { 1 } else { 0 })),
-                        Value::Float(f) => Some(Value::Int(if f == 0.0 { 1 } else { 0 })),
-                        _ => None,
-                    }
-                } else {
-                    None
+    fn check_condition(&mut self, condition: &Condition) {
+        match condition {
+            Condition::Expr(expr) => match self.check_expression(expr) {
+                Type::Bool | Type::Error => {}
+                found => {
+                    self.report(
+                        ErrorCode::TypeMismatch,
+                        format!("Expected a condition, found {}", found),
+                        expr.span(),
+                    );
                 }
-            }
-            _ => None,
+            },
         }
     }
 
-    
-
+    // Folds an expression that only refers to literals and declared constants
     fn evaluate_constant(&self, expr: &Expression) -> Option<Value> {
-        match expr {
-            Expression::Integer(n) => Some(Value::Int(*n)),
-            Expression::Float(n) => Some(Value::Float(*n)),
-            Expression::Literal(inner_expr) => {
-                // Unwrap the literal and evaluate the inner expression
-                self.evaluate_constant(inner_expr)
-            }
-            Expression::Binary { left, op, right } => {
-                if let (Some(left_val), Some(right_val)) =
-                    (self.evaluate_constant(left), self.evaluate_constant(right))
-                {
-                    match (left_val, right_val) {
-                        (Value::Int(left_int), Value::Int(right_int)) => {
-                            match op {
-                                BinaryOp::Add => Some(Value::Int(left_int + right_int)),
-                                BinaryOp::Subtract => Some(Value::Int(left_int - right_int)),
-                                BinaryOp::Multiply => Some(Value::Int(left_int * right_int)),
-                                BinaryOp::Divide => {
-                                    if right_int == 0 {
-                                        // Division by zero is caught in another check
-                                        None
-                                    } else {
-                                        Some(Value::Int(left_int / right_int))
-                                    }
-                                }
-                                _ => None, // Logical operators not supported in constant evaluation
-                            }
-                        }
-                        (Value::Float(left_float), Value::Float(right_float)) => {
-                            match op {
-                                BinaryOp::Add => Some(Value::Float(left_float + right_float)),
-                                BinaryOp::Subtract => Some(Value::Float(left_float - right_float)),
-                                BinaryOp::Multiply => Some(Value::Float(left_float * right_float)),
-                                BinaryOp::Divide => {
-                                    if right_float == 0.0 {
-                                        // Division by zero is caught in another check
-                                        None
-                                    } else {
-                                        Some(Value::Float(left_float / right_float))
-                                    }
-                                }
-                                _ => None, // Logical operators not supported in constant evaluation
-                            }
-                        }
-                        _ => None, // Mixed types not supported in constant evaluation
-                    }
-                } else {
-                    None
-                }
+        constant_propagation::evaluate(expr, &|name| match self.symbol_table.lookup(name) {
+            // Constants whose definition was wrong have no value
+            Some(entry) if matches!(entry.entity_type, EntityType::Constant | EntityType::Array { constant: true, .. }) => {
+                Some(entry.value.clone()).filter(|value| *value != Value::Undefined)
             }
-            Expression::Not(_) => None, // Not supported in constant evaluation
-            Expression::Var(var) => match var {
-                Variable::Simple(name) => {
-                    if let Some(entry) = self.symbol_table.lookup(name) {
-                        if let EntityType::Constant = entry.entity_type {
-                            Some(entry.value.clone())
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            },
             _ => None,
-        }
+        })
     }
 }
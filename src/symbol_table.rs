@@ -1,29 +1,68 @@
 // src/symbol_table.rs
 
+use crate::ast::Span;
 use std::collections::HashMap;
-use std::fmt::Write;
+use std::fmt::{self, Write};
+
+// Builtin function giving the number of characters of a string
+pub const BUILTIN_LEN: &str = "len";
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum EntityType {
     Variable,
     Constant,
-    Array { size: i32 },
+    // Sizes, outermost first; the elements of a constant array cannot be assigned
+    Array { dimensions: Vec<i32>, constant: bool },
+    // Parameters in declaration order, and the scope holding them and the locals
+    Function { params: Vec<(String, DataType)>, scope: usize },
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
     Int,
     Float,
+    Bool,
+    String,
+    Void, // Result of a procedure
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Int(i32),
     Float(f32),
+    Bool(bool),
+    String(String),
     Array(Vec<Value>),
     Undefined,
 }
 
+impl Value {
+    // Array of the given dimensions whose elements are all undefined
+    pub fn empty_array(dimensions: &[i32]) -> Value {
+        match dimensions.split_first() {
+            Some((size, inner)) => Value::Array(vec![Value::empty_array(inner); *size as usize]),
+            None => Value::Undefined,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            // Debug formatting keeps the decimal point, so 2.0 is not shown as the Int 2
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Undefined => write!(f, "-"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SymbolEntry {
     pub name: String,
@@ -32,120 +71,295 @@ pub struct SymbolEntry {
     pub value: Value,
     pub line: usize,
     pub column: usize,
+    pub span: Span,   // Where the name is declared
+    pub scope: usize, // Id of the scope the name is declared in
+}
+
+// Declarations of one block; the global scope has id 0 and no parent
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub id: usize,
+    pub name: String,
+    pub parent: Option<usize>,
+    pub symbols: HashMap<String, SymbolEntry>,
 }
 
+// Scopes are kept after they are exited so that the table can still be printed
+// and the interpreter can find every declaration
 pub struct SymbolTable {
-    pub table: HashMap<String, SymbolEntry>,
+    pub scopes: Vec<Scope>,
+    current: usize,
 }
 
-impl SymbolTable {
-    pub fn update_value(&mut self, name: &str, value: Value) -> Result<(), String> {
-        if let Some(entry) = self.table.get_mut(name) {
-            entry.value = value;
-            Ok(())
-        } else {
-            Err(format!("Cannot update undefined variable '{}'", name))
-        }
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            table: HashMap::new(),
+            scopes: vec![Scope {
+                id: 0,
+                name: "global".to_string(),
+                parent: None,
+                symbols: HashMap::new(),
+            }],
+            current: 0,
+        }
+    }
+
+    pub fn current_scope(&self) -> usize {
+        self.current
+    }
+
+    // Makes an existing scope current, e.g. to run the body of a function
+    pub fn set_current_scope(&mut self, id: usize) {
+        self.current = id;
+    }
+
+    // Opens a scope nested in the current one and makes it current
+    pub fn enter_scope(&mut self, name: &str) -> usize {
+        let id = self.scopes.len();
+        self.scopes.push(Scope {
+            id,
+            name: name.to_string(),
+            parent: Some(self.current),
+            symbols: HashMap::new(),
+        });
+        self.current = id;
+        id
+    }
+
+    pub fn exit_scope(&mut self) {
+        if let Some(parent) = self.scopes[self.current].parent {
+            self.current = parent;
         }
     }
 
-    pub fn insert(&mut self, entry: SymbolEntry) -> Result<(), String> {
-        if self.table.contains_key(&entry.name) {
-            Err(format!(
-                "Semantic Error: Double declaration of '{}' at line {}, column {}",
-                entry.name, entry.line, entry.column
-            ))
+    // Declares a name in the current scope; outer declarations may be shadowed
+    pub fn insert(&mut self, mut entry: SymbolEntry) -> Result<(), String> {
+        let symbols = &mut self.scopes[self.current].symbols;
+        if symbols.contains_key(&entry.name) {
+            Err(format!("Double declaration of '{}'", entry.name))
         } else {
-            self.table.insert(entry.name.clone(), entry);
+            entry.scope = self.current;
+            symbols.insert(entry.name.clone(), entry);
             Ok(())
         }
     }
 
+    // Finds the innermost declaration of a name, walking outward from the current scope
+    pub fn lookup(&self, name: &str) -> Option<&SymbolEntry> {
+        let mut scope = Some(self.current);
+        while let Some(id) = scope {
+            if let Some(entry) = self.scopes[id].symbols.get(name) {
+                return Some(entry);
+            }
+            scope = self.scopes[id].parent;
+        }
+        None
+    }
+
+    // Declaration of a name in the current scope only
+    pub fn lookup_current(&self, name: &str) -> Option<&SymbolEntry> {
+        self.scopes[self.current].symbols.get(name)
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut SymbolEntry> {
+        let mut scope = Some(self.current);
+        while let Some(id) = scope {
+            if self.scopes[id].symbols.contains_key(name) {
+                return self.scopes[id].symbols.get_mut(name);
+            }
+            scope = self.scopes[id].parent;
+        }
+        None
+    }
+
+    pub fn update_value(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if let Some(entry) = self.lookup_mut(name) {
+            entry.value = value;
+            Ok(())
+        } else {
+            Err(format!("Cannot update undefined variable '{}'", name))
+        }
+    }
+
+    // Stores an element of a possibly nested array, given one index per dimension
     pub fn update_array_element(
         &mut self,
         name: &str,
-        index: usize,
+        indices: &[usize],
         value: Value,
     ) -> Result<(), String> {
-        if let Some(entry) = self.table.get_mut(name) {
-            match &mut entry.value {
-                Value::Array(elements) => {
-                    if index < elements.len() {
-                        elements[index] = value;
-                        Ok(())
-                    } else {
-                        Err(format!(
-                            "Index {} out of bounds for array '{}' of size {}",
-                            index,
-                            name,
-                            elements.len()
-                        ))
+        if let Some(entry) = self.lookup_mut(name) {
+            let mut element = &mut entry.value;
+            for &index in indices {
+                element = match element {
+                    Value::Array(elements) => {
+                        let size = elements.len();
+                        elements.get_mut(index).ok_or_else(|| {
+                            format!("Index {} out of bounds for array '{}' of size {}", index, name, size)
+                        })?
                     }
-                }
-                _ => Err(format!("'{}' is not an array", name)),
+                    _ => return Err(format!("'{}' is not an array", name)),
+                };
             }
+            *element = value;
+            Ok(())
         } else {
             Err(format!("Undeclared array '{}'", name))
         }
     }
 
-    
-    pub fn lookup(&self, name: &str) -> Option<&SymbolEntry> {
-        self.table.get(name)
-    }
-
+    // Prints one table per scope, outermost first
     pub fn format_table(&self) -> String {
         let mut output = String::new();
-        writeln!(output, "+{:-<20}+{:-<15}+{:-<10}+{:-<15}+{:-<8}+{:-<8}+", 
-            "", "", "", "", "", "").unwrap();
-        writeln!(output, "| {:<18} | {:<13} | {:<8} | {:<13} | {:<6} | {:<6} |", 
-            "Name", "Entity Type", "Type", "Value", "Line", "Column").unwrap();
-        writeln!(output, "+{:-<20}+{:-<15}+{:-<10}+{:-<15}+{:-<8}+{:-<8}+", 
-            "", "", "", "", "", "").unwrap();
-        
-        let mut entries: Vec<&SymbolEntry> = self.table.values().collect();
+        for scope in &self.scopes {
+            match scope.parent {
+                Some(parent) => writeln!(
+                    output,
+                    "Scope {} ({}, inside scope {})",
+                    scope.id, scope.name, parent
+                )
+                .unwrap(),
+                None => writeln!(output, "Scope {} ({})", scope.id, scope.name).unwrap(),
+            }
+            output.push_str(&Self::format_scope(scope));
+        }
+        output
+    }
+
+    fn format_scope(scope: &Scope) -> String {
+        let mut entries: Vec<&SymbolEntry> = scope.symbols.values().collect();
         entries.sort_by(|a, b| a.name.cmp(&b.name));
         
-        for entry in entries {
-            let entity_type = match &entry.entity_type {
-                EntityType::Variable => "Variable".to_string(),
-                EntityType::Constant => "Constant".to_string(),
-                EntityType::Array { size } => format!("Array[{}]", size),
-            };
-            
-            let value_str = match &entry.value {
-                Value::Int(i) => i.to_string(),
-                Value::Float(f) => f.to_string(),
-                Value::Array(elements) => {
-                    let elements_str: Vec<String> = elements.iter().map(|v| {
-                        match v {
-                            Value::Int(n) => n.to_string(),
-                            Value::Float(n) => format!("{:.1}", n),
-                            Value::Undefined => "-".to_string(),
-                            Value::Array(_) => "[]".to_string(),
-                        }
-                    }).collect();
-                    format!("[{}]", elements_str.join(", "))
-                },
-                Value::Undefined => "-".to_string(),
-            };
-    
-            writeln!(output, "| {:<18} | {:<13} | {:<8} | {:<13} | {:<6} | {:<6} |", 
+        let rows: Vec<(&SymbolEntry, String, String)> = entries
+            .into_iter()
+            .map(|entry| {
+                let entity_type = match &entry.entity_type {
+                    EntityType::Variable => "Variable".to_string(),
+                    EntityType::Constant => "Constant".to_string(),
+                    EntityType::Array { dimensions, constant } => {
+                        let sizes: Vec<String> = dimensions.iter().map(|size| format!("[{}]", size)).collect();
+                        let kind = if *constant { "Constant" } else { "Array" };
+                        format!("{}{}", kind, sizes.concat())
+                    }
+                    EntityType::Function { params, .. } => format!("Function/{}", params.len()),
+                };
+                (entry, entity_type, entry.value.to_string())
+            })
+            .collect();
+        
+        // The entity type and value columns grow to fit array sizes and contents
+        let kind_width = rows.iter().map(|(_, kind, _)| kind.len()).fold(13, usize::max);
+        let value_width = rows.iter().map(|(_, _, value)| value.len()).fold(13, usize::max);
+        let border = format!("+{:-<20}+{:-<k$}+{:-<10}+{:-<v$}+{:-<8}+{:-<8}+", 
+            "", "", "", "", "", "", k = kind_width + 2, v = value_width + 2);
+        
+        let mut output = String::new();
+        writeln!(output, "{}", border).unwrap();
+        writeln!(output, "| {:<18} | {:<k$} | {:<8} | {:<v$} | {:<6} | {:<6} |", 
+            "Name", "Entity Type", "Type", "Value", "Line", "Column", k = kind_width, v = value_width).unwrap();
+        writeln!(output, "{}", border).unwrap();
+        
+        for (entry, entity_type, value_str) in rows {
+            writeln!(output, "| {:<18} | {:<k$} | {:<8} | {:<v$} | {:<6} | {:<6} |", 
                 entry.name, 
                 entity_type, 
                 format!("{:?}", entry.data_type), 
                 value_str, 
                 entry.line, 
-                entry.column
+                entry.column,
+                k = kind_width,
+                v = value_width
             ).unwrap();
         }
         
-        writeln!(output, "+{:-<20}+{:-<15}+{:-<10}+{:-<15}+{:-<8}+{:-<8}+", 
-            "", "", "", "", "", "").unwrap();
+        writeln!(output, "{}", border).unwrap();
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DataType, EntityType, SymbolEntry, SymbolTable, Value};
+    use crate::ast::Span;
+
+    fn variable(name: &str, value: i32) -> SymbolEntry {
+        SymbolEntry {
+            name: name.to_string(),
+            entity_type: EntityType::Variable,
+            data_type: DataType::Int,
+            value: Value::Int(value),
+            line: 1,
+            column: 1,
+            span: Span::default(),
+            scope: 0,
+        }
+    }
+
+    fn value_of(table: &SymbolTable, name: &str) -> Option<Value> {
+        table.lookup(name).map(|entry| entry.value.clone())
+    }
+
+    #[test]
+    fn lookup_finds_names_of_enclosing_scopes() {
+        let mut table = SymbolTable::new();
+        table.insert(variable("g", 1)).unwrap();
+        let outer = table.enter_scope("f");
+        table.enter_scope("block");
+
+        assert_eq!(value_of(&table, "g"), Some(Value::Int(1)));
+        assert_eq!(table.lookup("g").unwrap().scope, 0);
+        assert!(table.lookup_current("g").is_none());
+
+        table.exit_scope();
+        assert_eq!(table.current_scope(), outer);
+        table.exit_scope();
+        assert_eq!(table.current_scope(), 0);
+    }
+
+    #[test]
+    fn inner_declaration_shadows_outer_one() {
+        let mut table = SymbolTable::new();
+        table.insert(variable("x", 1)).unwrap();
+        let inner = table.enter_scope("f");
+        table.insert(variable("x", 2)).unwrap();
+
+        assert_eq!(value_of(&table, "x"), Some(Value::Int(2)));
+        assert_eq!(table.lookup("x").unwrap().scope, inner);
+
+        // Updates go to the innermost declaration and leave the outer one alone
+        table.update_value("x", Value::Int(3)).unwrap();
+        table.exit_scope();
+        assert_eq!(value_of(&table, "x"), Some(Value::Int(1)));
+        table.set_current_scope(inner);
+        assert_eq!(value_of(&table, "x"), Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn names_are_gone_after_their_scope_is_exited() {
+        let mut table = SymbolTable::new();
+        table.enter_scope("f");
+        table.insert(variable("local", 1)).unwrap();
+        assert!(table.lookup("local").is_some());
+
+        table.exit_scope();
+        assert!(table.lookup("local").is_none());
+        assert!(table.update_value("local", Value::Int(2)).is_err());
+        // The scope is kept so that the table can still be printed
+        assert_eq!(table.scopes.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_in_the_same_scope_is_rejected() {
+        let mut table = SymbolTable::new();
+        table.insert(variable("x", 1)).unwrap();
+        assert!(table.insert(variable("x", 2)).is_err());
+        table.enter_scope("f");
+        assert!(table.insert(variable("x", 2)).is_ok());
+    }
+}
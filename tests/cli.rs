@@ -0,0 +1,405 @@
+// tests/cli.rs
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// Runs the command line driver on a program given on stdin
+fn run_driver(command: &str, source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_Min_compiler"))
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("cannot start the compiler");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn unbounded_recursion_reports_the_call_limit() {
+    let source = "
+        MainPrgm deep;
+        Var
+        let r: Int;
+        function inf(k: Int): Int {
+          while (true) {
+            if (k > -1) then {
+              return inf(k + 1) + 1;
+            }
+            output(k);
+          }
+          return 0;
+        }
+        BeginPg
+        {
+          r := inf(0);
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(
+        stderr.contains("Too many nested calls while calling 'inf'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn for_loop_up_to_the_largest_int_ends() {
+    let source = "
+        MainPrgm top;
+        Var
+        let i: Int;
+        BeginPg
+        {
+          for i from 2147483645 to 2147483647 step 1 {
+            output(i);
+          }
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        ["2147483645", "2147483646", "2147483647"]
+    );
+}
+
+#[test]
+fn for_loop_stops_before_stepping_past_the_end() {
+    let source = "
+        MainPrgm past;
+        Var
+        let i: Int;
+        BeginPg
+        {
+          for i from 2147483640 to 2147483647 step 5 {
+            output(i);
+          }
+          output(i);
+          for i from 10 to 0 step -3 {
+            output(i);
+          }
+          output(i);
+          for i from 5 to 0 step 1 {
+            output(i);
+          }
+          output(i);
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // The loop variable keeps the last value the body ran with
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "2147483640",
+            "2147483645",
+            "2147483645",
+            "10",
+            "7",
+            "4",
+            "1",
+            "1",
+            "5"
+        ]
+    );
+}
+
+#[test]
+fn for_loop_with_a_zero_step_stops() {
+    let source = "
+        MainPrgm zero;
+        Var
+        let i, s: Int;
+        BeginPg
+        {
+          s := 0;
+          for i from 1 to 3 step s {
+            output(i);
+          }
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains("zero"), "{}", stderr);
+}
+
+#[test]
+fn call_with_a_negative_literal_argument() {
+    let source = "
+        MainPrgm neg;
+        Var
+        let r: Int;
+        function abs1(k: Int): Int {
+          if (k < 0) then {
+            return -k;
+          }
+          return k;
+        }
+        BeginPg
+        {
+          r := abs1(-5);
+          output(r);
+          output(-3);
+          output((-5 + 1));
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), ["5", "-3", "-4"]);
+}
+
+#[test]
+fn oversized_array_is_a_semantic_error() {
+    let source = "
+        MainPrgm big;
+        Var
+        let m: [[Int; 50000]; 50000];
+        BeginPg
+        {
+          m[0][0] := 1;
+        }
+        EndPg;
+    ";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("error[E0027]"), "{}", stderr);
+}
+
+#[test]
+fn switch_runs_the_default_for_a_value_without_a_case() {
+    let source = "
+        MainPrgm gap;
+        Var
+        let x: Int;
+        BeginPg
+        {
+          for x from 0 to 5 step 1 {
+            switch (x) {
+              case 1: { output(10); }
+              case 2, 4: { output(20); }
+              default: { output(0); }
+            }
+          }
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        ["0", "10", "20", "0", "20", "0"]
+    );
+}
+
+#[test]
+fn and_or_skip_the_right_operand() {
+    // Evaluating `10 / zero()` would stop the program with a division by zero
+    let source = "
+        MainPrgm sc;
+        Var
+        let x: Int;
+        let b: Bool;
+        function zero(): Int {
+          return 0;
+        }
+        BeginPg
+        {
+          x := 0;
+          if ((x > 0) AND (10 / zero() > 1)) then {
+            output(1);
+          }
+          if ((x == 0) OR (10 / zero() > 1)) then {
+            output(2);
+          }
+          b := (x > 0) AND (10 / zero() > 1);
+          output(b);
+        }
+        EndPg;
+    ";
+    let output = run_driver("run", source);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), ["2", "false"]);
+}
+
+#[test]
+fn syntax_error_lists_the_expected_tokens_and_context() {
+    let source = "MainPrgm p;
+Var
+let x: Int;
+BeginPg
+{
+  x := 1
+  output(x);
+}
+EndPg;
+";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(
+        stderr.contains("error[E0010]: expected `!=`, `%`, `*`, `**`, `+`, `-`, `/`, `;`, "),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("found `output`"), "{}", stderr);
+    assert!(stderr.contains(":7:3"), "{}", stderr);
+    assert!(
+        stderr.contains("note: while parsing an assignment"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn syntax_error_at_end_of_input() {
+    let source = "MainPrgm p;
+Var
+let x: Int;
+BeginPg
+{
+  x := 1;
+";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("error[E0011]: expected "), "{}", stderr);
+    assert!(stderr.contains("found end of input"), "{}", stderr);
+}
+
+#[test]
+fn syntax_errors_are_all_reported_after_recovery() {
+    let source = "MainPrgm p;
+Var
+let x: Int;
+let y Int;
+BeginPg
+{
+  x := ;
+  if (x > ) then { x := 1; }
+  output(x);
+}
+EndPg;
+";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    // One error in a declaration and two in statements, each in its own context
+    assert_eq!(stderr.matches("error[E0010]").count(), 3, "{}", stderr);
+    assert!(stderr.contains(":4:7"), "{}", stderr);
+    assert!(
+        stderr.contains("note: while parsing a variable declaration"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(":7:8"), "{}", stderr);
+    assert!(
+        stderr.contains("note: while parsing an assignment"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(":8:11"), "{}", stderr);
+    assert!(
+        stderr.contains("note: while parsing an `if` condition"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("aborting due to 3 previous errors"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn lexical_errors_are_reported_with_their_codes() {
+    let source = "MainPrgm p;
+Var
+let x: Int;
+let 9lives, a__b, c_: Int;
+BeginPg
+{
+  x := 99999999999 # 2;
+}
+EndPg;
+";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    for expected in [
+        "error[E0013]: identifier `9lives` starts with a digit",
+        "error[E0013]: identifier `a__b` contains consecutive underscores",
+        "error[E0013]: identifier `c_` ends with an underscore",
+        "error[E0014]: integer literal `99999999999` is too large for an Int",
+        "error[E0012]: unexpected character sequence `#`",
+    ] {
+        assert!(stderr.contains(expected), "{}", stderr);
+    }
+    assert!(
+        stderr.contains("aborting due to 5 previous errors"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn run_of_invalid_characters_is_one_error() {
+    let source = "MainPrgm p;
+Var
+let x: Int;
+BeginPg
+{
+  x := 1 @#$ 2;
+}
+EndPg;
+";
+    let output = run_driver("check", source);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert_eq!(stderr.matches("error[E0012]").count(), 1, "{}", stderr);
+}